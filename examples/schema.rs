@@ -3,8 +3,15 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use reencryption_key_hodler::msg::{HandleMsg, InitMsg, QueryMsg, ReencryptionKeyResponse};
-use reencryption_key_hodler::state::State;
+use reencryption_key_hodler::msg::{
+    ActivityStatementResponse, AllKeysResponse, CiphertextResponse, CollectedFeesResponse,
+    ConfigResponse, ExecuteAnswer, ExecuteMsg, FragmentAssignmentsResponse, FragmentResponse,
+    GranteesResponse, HealthResponse, InstantiateMsg, LabelsResponse, LatestVersionResponse,
+    MaintenanceStatusResponse, MigrateMsg, OwnerResponse, ProxiesResponse, QueryMsg, ReceiveMsg,
+    ReencryptionKeyResponse, ReleaseStatusResponse, RetirementStatusResponse, SetCreditsResponse,
+    SettersResponse, TransparencyEntriesResponse, TransparencyHeadResponse,
+};
+use reencryption_key_hodler::state::{ContractVersion, State, Stats};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -12,9 +19,34 @@ fn main() {
     create_dir_all(&out_dir).unwrap();
     remove_schemas(&out_dir).unwrap();
 
-    export_schema(&schema_for!(InitMsg), &out_dir);
-    export_schema(&schema_for!(HandleMsg), &out_dir);
+    export_schema(&schema_for!(InstantiateMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(ExecuteAnswer), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
+    export_schema(&schema_for!(Stats), &out_dir);
+    export_schema(&schema_for!(ContractVersion), &out_dir);
     export_schema(&schema_for!(ReencryptionKeyResponse), &out_dir);
+    export_schema(&schema_for!(LatestVersionResponse), &out_dir);
+    export_schema(&schema_for!(LabelsResponse), &out_dir);
+    export_schema(&schema_for!(GranteesResponse), &out_dir);
+    export_schema(&schema_for!(FragmentResponse), &out_dir);
+    export_schema(&schema_for!(FragmentAssignmentsResponse), &out_dir);
+    export_schema(&schema_for!(ProxiesResponse), &out_dir);
+    export_schema(&schema_for!(ReleaseStatusResponse), &out_dir);
+    export_schema(&schema_for!(OwnerResponse), &out_dir);
+    export_schema(&schema_for!(SettersResponse), &out_dir);
+    export_schema(&schema_for!(RetirementStatusResponse), &out_dir);
+    export_schema(&schema_for!(MaintenanceStatusResponse), &out_dir);
+    export_schema(&schema_for!(TransparencyHeadResponse), &out_dir);
+    export_schema(&schema_for!(TransparencyEntriesResponse), &out_dir);
+    export_schema(&schema_for!(ActivityStatementResponse), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(HealthResponse), &out_dir);
+    export_schema(&schema_for!(AllKeysResponse), &out_dir);
+    export_schema(&schema_for!(CiphertextResponse), &out_dir);
+    export_schema(&schema_for!(CollectedFeesResponse), &out_dir);
+    export_schema(&schema_for!(ReceiveMsg), &out_dir);
+    export_schema(&schema_for!(SetCreditsResponse), &out_dir);
 }
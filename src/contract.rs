@@ -1,174 +1,6971 @@
 use cosmwasm_std::{
-    debug_print, to_binary, Api, Binary, Env, Extern, HandleResponse, InitResponse, Querier,
-    StdError, StdResult, Storage,
+    entry_point, from_binary, to_binary, Addr, Api, BankMsg, Binary, BlockInfo, CanonicalAddr,
+    Coin, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage, Uint128,
+    WasmMsg,
 };
+use sha2::{Digest, Sha256};
 
-use crate::msg::{ReencryptionKeyResponse, HandleMsg, InitMsg, QueryMsg};
-use crate::state::{config, config_read, State};
+use crate::error::ContractError;
+use crate::msg::{
+    ActivityStatementResponse, AllKeysResponse, CeremonyStatusResponse, CiphertextResponse,
+    CollectedFeesResponse, ConfigResponse, ExecuteAnswer, ExecuteMsg, FragmentAssignmentsResponse,
+    FragmentResponse, GranteesResponse, HealthResponse, InstantiateMsg, KeyCursor, KeyEntry,
+    KeyMetadata, KeyStatus, LabelsResponse, LatestVersionResponse, MaintenanceStatusResponse,
+    MigrateMsg, OwnerResponse, ProxiesResponse, QueryMsg, ReceiveMsg, ReencryptionKeyResponse,
+    ReleaseStatusResponse, RetirementStage, RetirementStatusResponse, SetCreditsResponse,
+    SettersResponse, Snip20Msg, TokenFeeMsg, TransparencyEntriesResponse, TransparencyHeadResponse,
+    ViewingKeyInfoResponse,
+};
+use crate::response::ResponseBuilder;
+use crate::state::{
+    activity, activity_lens, activity_lens_read, activity_read, ceremonies, ceremonies_read,
+    ciphertext_hashes, ciphertext_hashes_read, ciphertexts, ciphertexts_read, collected_fees,
+    collected_fees_read, collected_tokens, collected_tokens_read, config, config_read,
+    contract_config, contract_config_read, contract_version, contract_version_read,
+    dead_man_switches, dead_man_switches_read, fragment_sets, fragment_sets_read, grants,
+    grants_read, key_owners, key_owners_read, key_versions, key_versions_read, keys, keys_read,
+    labels, labels_read, log_ids, log_ids_read, move_viewing_key, outstanding_credits,
+    outstanding_credits_read, proxies, proxies_read, purge_progress, purge_progress_read,
+    read_prng_seed, read_viewing_key, retrieval_tokens, retrieval_tokens_read, set_credits,
+    set_credits_read, stats, stats_counted, stats_counted_read, stats_read,
+    transparency_checkpoints, transparency_checkpoints_read, transparency_entries,
+    transparency_entries_read, transparency_head, transparency_head_read, write_prng_seed,
+    write_viewing_key, ActivityEntry, Ceremony, Config, ContractVersion, DeadManSwitch, Expiration,
+    Fragment, FragmentSet, KeyRecord, KeyType, Maintenance, PurgeProgress, Retirement,
+    RetrievalToken, SetMode, State, Stats, StoredAddr, StoredCiphertext, TokenFee,
+    TransparencyEntry, TransparencyHead, DEFAULT_MAX_KEY_LENGTH,
+};
+use crate::viewing_key::{new_retrieval_token, new_salt, ViewingKey, SALT_SIZE, VIEWING_KEY_SIZE};
+
+pub const CONTRACT_NAME: &str = env!("CARGO_PKG_NAME");
+pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Shortest notice the owner can give before remaining data may be purged (90 days).
+pub const MIN_RETIREMENT_NOTICE: u64 = 90 * 24 * 60 * 60;
+
+/// How many key owners a single `PurgeRetired` call removes.
+pub const PURGE_BATCH_SIZE: u64 = 50;
+
+/// A transparency log checkpoint is recorded after every this many entries.
+pub const CHECKPOINT_INTERVAL: u64 = 16;
+
+/// Label a key is stored under when `Set` is given none.
+pub const DEFAULT_LABEL: &str = "default";
+pub const MAX_LABEL_LENGTH: usize = 64;
+
+pub const MAX_DESCRIPTION_LENGTH: usize = 256;
+pub const MAX_CID_LENGTH: usize = 128;
+// Umbral capsules are 98 bytes; this leaves room for other schemes
+pub const MAX_CAPSULE_LENGTH: usize = 256;
+// enough for key envelopes and pointers; documents themselves belong off-chain
+pub const MAX_CIPHERTEXT_LENGTH: usize = 2048;
+// keeps purging and migrating an owner within a single message
+pub const MAX_CIPHERTEXTS: usize = 32;
+// archived versions kept per key, so that removing a key stays within a single message
+pub const MAX_KEY_VERSIONS: u64 = 32;
 
-pub fn init<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
+/// Most entries a single `BatchSet` stores.
+pub const MAX_BATCH_SIZE: usize = 50;
+
+const DEFAULT_PAGE_LIMIT: u32 = 10;
+const MAX_PAGE_LIMIT: u32 = 30;
+
+/// Most key owners a single `ListAllKeys` page looks at, whether or not they have keys.
+pub const MAX_OWNERS_SCANNED: u64 = 50;
+
+#[entry_point]
+pub fn instantiate(
+    deps: DepsMut,
     env: Env,
-    _msg: InitMsg,
-) -> StdResult<InitResponse> {
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    let owner = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender.clone(),
+    };
+    // a limit or fee of zero would block every Set rather than lift the limit
+    if msg.max_keys_per_owner == Some(0) {
+        return Err(StdError::generic_err("max keys per owner must not be zero").into());
+    }
+    if msg
+        .set_fee
+        .as_ref()
+        .map_or(false, |fee| fee.amount.is_zero())
+    {
+        return Err(StdError::generic_err("set fee must not be zero").into());
+    }
+    if msg.default_inactivity_window == Some(0) {
+        return Err(StdError::generic_err("inactivity window must not be zero").into());
+    }
     let state = State {
-        reencryption_key: [0; 32],
-        owner: deps.api.canonical_address(&env.message.sender)?,
+        owner: deps.api.addr_canonicalize(owner.as_str())?,
+        owner_human: owner,
+        pending_owner: None,
+        set_mode: SetMode::Open,
+        setters: vec![],
+        key_owners: 0,
+        retirement: None,
+        maintenance: None,
+        paused: false,
+        guardian: None,
+        frozen: false,
+    };
+    let parameters = Config {
+        max_keys_per_owner: msg.max_keys_per_owner,
+        set_fee: msg.set_fee,
+        key_type: msg.key_type.unwrap_or(KeyType::Any),
+        max_key_length: DEFAULT_MAX_KEY_LENGTH,
+        default_inactivity_window: msg.default_inactivity_window,
+        token_set_fee: None,
     };
 
-    config(&mut deps.storage).save(&state)?;
+    config(deps.storage).save(&state)?;
+    contract_config(deps.storage).save(&parameters)?;
+    stats(deps.storage).save(&Stats::default())?;
+    contract_version(deps.storage).save(&ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: CONTRACT_VERSION.to_string(),
+    })?;
+
+    // viewing keys also mix in the sender's entropy, and every new key is folded back in
+    let mut hasher = Sha256::new();
+    hasher.update(info.sender.as_bytes());
+    hasher.update(env.block.chain_id.as_bytes());
+    hasher.update(env.block.height.to_be_bytes());
+    hasher.update(env.block.time.seconds().to_be_bytes());
+    write_prng_seed(deps.storage, &hasher.finalize());
+    transparency_head(deps.storage).save(&TransparencyHead {
+        len: 0,
+        head: Binary(vec![0; 32]),
+    })?;
+
+    deps.api
+        .debug(&format!("Contract was initialized by {}", info.sender));
 
-    debug_print!("Contract was initialized by {}", env.message.sender);
+    Ok(Response::default())
+}
+
+/// Upgrades the stored state to this code's layout. Only code of the same contract may take
+/// over, and the stored version is bumped to this one.
+#[entry_point]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = contract_version_read(deps.storage).load()?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(StdError::generic_err(format!(
+            "cannot migrate from {} to {}",
+            stored.contract, CONTRACT_NAME
+        ))
+        .into());
+    }
+    // unsalted viewing key hashes can't be converted, so they are left behind under their old
+    // namespace and holders create new keys
+    if contract_config_read(deps.storage).may_load()?.is_none() {
+        contract_config(deps.storage).save(&Config::default())?;
+    }
+    if stats_read(deps.storage).may_load()?.is_none() {
+        // the totals are counted from the key owner index in pages, by RecountStats
+        let key_owners = config_read(deps.storage).load()?.key_owners;
+        stats(deps.storage).save(&Stats {
+            recount_next: if key_owners > 0 { Some(0) } else { None },
+            ..Stats::default()
+        })?;
+    }
+    contract_version(deps.storage).save(&ContractVersion {
+        contract: CONTRACT_NAME.to_string(),
+        version: CONTRACT_VERSION.to_string(),
+    })?;
 
-    Ok(InitResponse::default())
+    deps.api
+        .debug(&format!("migrated from version {}", stored.version));
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
 }
 
-pub fn handle<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
+#[entry_point]
+pub fn execute(
+    deps: DepsMut,
     env: Env,
-    msg: HandleMsg,
-) -> StdResult<HandleResponse> {
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    let state = config_read(deps.storage).load()?;
+    if let Some(maintenance) = state.maintenance {
+        match msg {
+            ExecuteMsg::SetMaintenanceMode { .. } | ExecuteMsg::ClearMaintenanceMode {} => {}
+            _ => {
+                return Err(ContractError::MaintenanceMode {
+                    eta: maintenance.eta,
+                })
+            }
+        }
+    }
+    if state.paused {
+        match msg {
+            ExecuteMsg::Set { .. }
+            | ExecuteMsg::BatchSet { .. }
+            | ExecuteMsg::Grant { .. }
+            | ExecuteMsg::Reset { .. }
+            | ExecuteMsg::Delete { .. }
+            | ExecuteMsg::MintRetrievalToken { .. }
+            | ExecuteMsg::SetDeadManSwitch { .. }
+            | ExecuteMsg::SetCeremony { .. }
+            | ExecuteMsg::SetFragments { .. }
+            | ExecuteMsg::AssignFragment { .. }
+            | ExecuteMsg::StoreCapsule { .. }
+            | ExecuteMsg::StoreCiphertext { .. }
+            | ExecuteMsg::DeleteCiphertext { .. } => return Err(ContractError::ContractPaused {}),
+            _ => {}
+        }
+    }
+    // Set and BatchSet collect their own funds; whatever is sent with anything else is kept for
+    // the owner to withdraw rather than left unaccounted for
+    if !matches!(msg, ExecuteMsg::Set { .. } | ExecuteMsg::BatchSet { .. }) {
+        collect_fees(deps.storage, &info.funds)?;
+    }
+
     match msg {
-        HandleMsg::Set { reencryption_key} => try_set_reencryption_key(deps, env, reencryption_key),
-        HandleMsg::Reset { } => try_reset(deps, env),
+        ExecuteMsg::Set {
+            label,
+            reencryption_key,
+            expires_at,
+            description,
+            document_cid,
+        } => try_set_reencryption_key(
+            deps,
+            env,
+            info,
+            KeyEntry {
+                label,
+                reencryption_key,
+                expires_at,
+                description,
+                document_cid,
+            },
+        ),
+        ExecuteMsg::BatchSet { entries } => try_batch_set(deps, env, info, entries),
+        ExecuteMsg::Reset { label } => try_reset(deps, env, info, label),
+        ExecuteMsg::Delete { label } => try_delete(deps, env, info, label),
+        ExecuteMsg::StoreCapsule { label, capsule } => {
+            try_store_capsule(deps, env, info, label, capsule)
+        }
+        ExecuteMsg::StoreCiphertext {
+            doc_hash,
+            ciphertext,
+        } => try_store_ciphertext(deps, env, info, doc_hash, ciphertext),
+        ExecuteMsg::DeleteCiphertext { doc_hash } => {
+            try_delete_ciphertext(deps, env, info, doc_hash)
+        }
+        ExecuteMsg::Retire { notice_period } => try_retire(deps, env, info, notice_period),
+        ExecuteMsg::CancelRetirement {} => try_cancel_retirement(deps, env, info),
+        ExecuteMsg::PurgeRetired {} => try_purge_retired(deps, env, info),
+        ExecuteMsg::SetMaintenanceMode { eta } => try_set_maintenance_mode(deps, env, info, eta),
+        ExecuteMsg::ClearMaintenanceMode {} => try_clear_maintenance_mode(deps, env, info),
+        ExecuteMsg::Pause {} => try_set_paused(deps, env, info, true),
+        ExecuteMsg::Unpause {} => try_set_paused(deps, env, info, false),
+        ExecuteMsg::SetGuardian { guardian } => try_set_guardian(deps, env, info, guardian),
+        ExecuteMsg::Freeze {} => try_set_frozen(deps, env, info, true),
+        ExecuteMsg::Unfreeze {} => try_set_frozen(deps, env, info, false),
+        ExecuteMsg::CreateViewingKey { entropy } => {
+            try_create_viewing_key(deps, env, info, entropy)
+        }
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            try_transfer_ownership(deps, env, info, new_owner)
+        }
+        ExecuteMsg::AcceptOwnership {} => try_accept_ownership(deps, env, info),
+        ExecuteMsg::AddSetter { address } => try_add_setter(deps, env, info, address),
+        ExecuteMsg::RemoveSetter { address } => try_remove_setter(deps, env, info, address),
+        ExecuteMsg::SetSetMode { mode } => try_set_set_mode(deps, env, info, mode),
+        ExecuteMsg::Withdraw { amount, recipient } => {
+            try_withdraw(deps, env, info, amount, recipient)
+        }
+        ExecuteMsg::WithdrawTokens { amount, recipient } => {
+            try_withdraw_tokens(deps, env, info, amount, recipient)
+        }
+        ExecuteMsg::SetTokenFee { token_fee } => try_set_token_fee(deps, env, info, token_fee),
+        ExecuteMsg::Receive {
+            from, amount, msg, ..
+        } => try_receive(deps, env, info, from, amount, msg),
+        ExecuteMsg::UpdateConfig {
+            max_keys_per_owner,
+            set_fee,
+            default_inactivity_window,
+        } => try_update_config(
+            deps,
+            env,
+            info,
+            max_keys_per_owner,
+            set_fee,
+            default_inactivity_window,
+        ),
+        ExecuteMsg::SetKeyType { key_type } => try_set_key_type(deps, env, info, key_type),
+        ExecuteMsg::SetMaxKeyLength { max_key_length } => {
+            try_set_max_key_length(deps, env, info, max_key_length)
+        }
+        ExecuteMsg::Grant { grantee } => try_grant(deps, env, info, grantee),
+        ExecuteMsg::Revoke { grantee } => try_revoke(deps, env, info, grantee),
+        ExecuteMsg::BatchRevoke { grantees } => try_batch_revoke(deps, env, info, grantees),
+        ExecuteMsg::MintRetrievalToken {
+            owner,
+            label,
+            expires_at,
+        } => try_mint_retrieval_token(deps, env, info, owner, label, expires_at),
+        ExecuteMsg::RedeemRetrievalToken { token } => {
+            try_redeem_retrieval_token(deps, env, info, token)
+        }
+        ExecuteMsg::MigrateAddresses { start, limit } => {
+            try_migrate_addresses(deps, env, info, start, limit)
+        }
+        ExecuteMsg::SetDeadManSwitch {
+            inactivity_window,
+            beneficiaries,
+        } => try_set_dead_man_switch(deps, env, info, inactivity_window, beneficiaries),
+        ExecuteMsg::ClearDeadManSwitch {} => try_clear_dead_man_switch(deps, env, info),
+        ExecuteMsg::Heartbeat {} => try_heartbeat(deps, env, info),
+        ExecuteMsg::SetCeremony {
+            participants,
+            window,
+        } => try_set_ceremony(deps, env, info, participants, window),
+        ExecuteMsg::ClearCeremony {} => try_clear_ceremony(deps, env, info),
+        ExecuteMsg::CheckIn { owner } => try_check_in(deps, env, info, owner),
+        ExecuteMsg::SetFragments {
+            fragments,
+            threshold,
+            proxies,
+        } => try_set_fragments(deps, env, info, fragments, threshold, proxies),
+        ExecuteMsg::ClearFragments {} => try_clear_fragments(deps, env, info),
+        ExecuteMsg::AssignFragment { index, proxy } => {
+            try_assign_fragment(deps, env, info, index, proxy)
+        }
+        ExecuteMsg::UnassignFragment { index } => try_unassign_fragment(deps, env, info, index),
+        ExecuteMsg::RegisterProxy {} => try_register_proxy(deps, env, info),
+        ExecuteMsg::DeregisterProxy {} => try_deregister_proxy(deps, env, info),
+        ExecuteMsg::Purge { start, limit } => try_purge(deps, env, info, start, limit),
+        ExecuteMsg::RecountStats { limit } => try_recount_stats(deps, env, info, limit),
+    }
+}
+
+fn label_or_default(label: Option<String>) -> String {
+    label.unwrap_or_else(|| DEFAULT_LABEL.to_string())
+}
+
+pub fn try_set_reencryption_key(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    entry: KeyEntry,
+) -> Result<Response, ContractError> {
+    let (label, version) = set_keys(deps.branch(), &env, &info, vec![entry])?.remove(0);
+
+    deps.api
+        .debug(&format!("reencryption key registered by {}", info.sender));
+
+    Ok(ResponseBuilder::new("set")
+        .log("owner", &info.sender)
+        .log("label", label)
+        .log("version", version)
+        .log("height", env.block.height)
+        .build())
+}
+
+/// Stores every entry or, if any of them is rejected, none. Each one costs a Set fee.
+pub fn try_batch_set(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    entries: Vec<KeyEntry>,
+) -> Result<Response, ContractError> {
+    if entries.is_empty() {
+        return Err(StdError::generic_err("at least one entry is required").into());
+    }
+    if entries.len() > MAX_BATCH_SIZE {
+        return Err(ContractError::QuotaExceeded {
+            what: "entries per batch".to_string(),
+            max: MAX_BATCH_SIZE as u64,
+        });
+    }
+    let stored = set_keys(deps, &env, &info, entries)?;
+
+    let mut response = ResponseBuilder::new("batch_set")
+        .log("owner", &info.sender)
+        .log("height", env.block.height);
+    for (label, version) in stored {
+        response = response.log("label", label).log("version", version);
+    }
+    Ok(response.build())
+}
+
+/// Checks and then stores `entries` as the sender's keys, returning the label and new
+/// version of each.
+fn set_keys(
+    deps: DepsMut,
+    env: &Env,
+    info: &MessageInfo,
+    entries: Vec<KeyEntry>,
+) -> Result<Vec<(String, u64)>, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let mut state = config_read(deps.storage).load()?;
+    check_can_store(&state, &sender_address_raw)?;
+    let parameters = contract_config_read(deps.storage).load()?;
+    let fee = charge_set_fee(
+        deps.storage,
+        &parameters,
+        &sender_address_raw,
+        &info.funds,
+        entries.len() as u64,
+    )?;
+    let mut checked: Vec<(String, KeyRecord)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let KeyEntry {
+            label,
+            reencryption_key: key,
+            expires_at,
+            description,
+            document_cid,
+        } = entry;
+        let label = label_or_default(label);
+        if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+            return Err(ContractError::InvalidLabel {
+                max: MAX_LABEL_LENGTH,
+            });
+        }
+        if checked.iter().any(|(checked, _)| checked == &label) {
+            return Err(StdError::generic_err(format!("{} is given more than once", label)).into());
+        }
+        if key.len() > parameters.max_key_length as usize {
+            return Err(ContractError::InvalidKeyLength {
+                len: key.len(),
+                max: parameters.max_key_length,
+            });
+        }
+        parameters
+            .key_type
+            .validate(&key)
+            .map_err(|reason| ContractError::InvalidKey {
+                reason: reason.to_string(),
+            })?;
+        if expires_at.map_or(false, |expires_at| expires_at.is_expired(&env.block)) {
+            return Err(ContractError::ExpiryInPast {});
+        }
+        validate_metadata(description.as_deref(), document_cid.as_deref())?;
+        checked.push((
+            label,
+            KeyRecord {
+                reencryption_key: key,
+                version: 0,
+                expires_at,
+                description,
+                document_cid,
+                capsule: None,
+            },
+        ));
+    }
+
+    let key_owner = StoredAddr {
+        canonical: sender_address_raw.clone(),
+        human: info.sender.clone(),
+    };
+    let mut owner_labels = index_key_owner(deps.storage, &mut state, key_owner)?;
+    let labels_before = owner_labels.len();
+    let mut stored = Vec::with_capacity(checked.len());
+    for (label, mut next) in checked {
+        if !owner_labels.contains(&label) {
+            if let Some(max) = parameters.max_keys_per_owner {
+                if owner_labels.len() >= max as usize {
+                    return Err(ContractError::QuotaExceeded {
+                        what: "keys per owner".to_string(),
+                        max: max as u64,
+                    });
+                }
+            }
+            owner_labels.push(label.clone());
+        }
+
+        append_key_hash(
+            deps.storage,
+            env.block.height,
+            &sender_address_raw,
+            &next.reencryption_key,
+        )?;
+        record_activity(
+            deps.storage,
+            env.block.height,
+            &sender_address_raw,
+            "set",
+            Some(label.as_str()),
+            None,
+            fee.as_ref(),
+        )?;
+        let existing = keys_read(deps.storage, &sender_address_raw).may_load(label.as_bytes())?;
+        next.capsule = existing
+            .as_ref()
+            .and_then(|existing| existing.capsule.clone());
+        let version = rotate_key(deps.storage, &sender_address_raw, &label, existing, next)?;
+        stored.push((label, version));
+    }
+    if owner_labels.len() != labels_before {
+        labels(deps.storage).save(sender_address_raw.as_slice(), &owner_labels)?;
+    }
+    let added = (owner_labels.len() - labels_before) as u64;
+    update_stats(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        |totals| {
+            totals.keys += added;
+            if labels_before == 0 && added > 0 {
+                totals.owners += 1;
+            }
+        },
+    )?;
+    collect_fees(deps.storage, &info.funds)?;
+
+    Ok(stored)
+}
+
+/// Checks `count` Sets are paid for, either by `funds` or, failing that, by as many of
+/// `payer`'s token credits, which are used up. Returns the fee paid per Set; a token fee is
+/// given as a coin whose denom is the token's address.
+fn charge_set_fee(
+    storage: &mut dyn Storage,
+    parameters: &Config,
+    payer: &CanonicalAddr,
+    funds: &[Coin],
+    count: u64,
+) -> Result<Option<Coin>, ContractError> {
+    let native_fee = parameters.set_fee.as_ref().map(|fee| Coin {
+        denom: fee.denom.clone(),
+        amount: fee.amount * Uint128::from(count),
+    });
+    if let Some(total) = &native_fee {
+        if funds
+            .iter()
+            .any(|coin| coin.denom == total.denom && coin.amount >= total.amount)
+        {
+            return Ok(parameters.set_fee.clone());
+        }
+    }
+    let token_fee = match &parameters.token_set_fee {
+        Some(token_fee) => Coin {
+            denom: token_fee.token.to_string(),
+            amount: token_fee.amount,
+        },
+        None => {
+            return match native_fee {
+                Some(total) => Err(ContractError::InsufficientFee { required: total }),
+                None => Ok(None),
+            }
+        }
+    };
+    let credits = set_credits_read(storage)
+        .may_load(payer.as_slice())?
+        .unwrap_or_default();
+    // coins sent along with credits would pay for nothing, so they are refused rather than
+    // kept
+    if credits < count || !funds.is_empty() {
+        return Err(ContractError::InsufficientFee {
+            required: native_fee.unwrap_or(Coin {
+                denom: token_fee.denom,
+                amount: token_fee.amount * Uint128::from(count),
+            }),
+        });
+    }
+    set_credits(storage).save(payer.as_slice(), &(credits - count))?;
+    let outstanding = outstanding_credits_read(storage)
+        .may_load()?
+        .unwrap_or_default();
+    outstanding_credits(storage).save(&outstanding.saturating_sub(count))?;
+    Ok(Some(token_fee))
+}
+
+/// Adds `funds` to the fees the owner can withdraw. Everything sent along is kept, including
+/// any amount above the fee.
+fn collect_fees(storage: &mut dyn Storage, funds: &[Coin]) -> StdResult<()> {
+    if funds.is_empty() {
+        return Ok(());
+    }
+    let mut collected = collected_fees_read(storage).may_load()?.unwrap_or_default();
+    for coin in funds {
+        match collected.iter_mut().find(|total| total.denom == coin.denom) {
+            Some(total) => total.amount += coin.amount,
+            None => collected.push(coin.clone()),
+        }
+    }
+    collected_fees(storage).save(&collected)
+}
+
+/// Checks `sender` may store keys: the contract must not be retiring, and while the set
+/// mode is closed only the owner and the setters may.
+fn check_can_store(state: &State, sender: &CanonicalAddr) -> Result<(), ContractError> {
+    if state.set_mode == SetMode::Closed
+        && sender != &state.owner
+        && !state
+            .setters
+            .iter()
+            .any(|setter| &setter.canonical == sender)
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+    if state.retirement.is_some() {
+        return Err(ContractError::Retiring {});
+    }
+    Ok(())
+}
+
+/// Loads `key_owner`'s label list, first adding them to the key owner index if they have
+/// never stored anything.
+fn index_key_owner(
+    storage: &mut dyn Storage,
+    state: &mut State,
+    key_owner: StoredAddr,
+) -> StdResult<Vec<String>> {
+    if let Some(owner_labels) = labels_read(storage).may_load(key_owner.canonical.as_slice())? {
+        return Ok(owner_labels);
+    }
+    key_owners(storage).save(&state.key_owners.to_be_bytes(), &key_owner)?;
+    labels(storage).save(key_owner.canonical.as_slice(), &Vec::new())?;
+    state.key_owners += 1;
+    config(storage).save(state)?;
+    Ok(vec![])
+}
+
+pub fn try_set_fragments(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    fragments: Vec<Binary>,
+    threshold: u8,
+    proxies: Vec<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+    check_can_store(&state, &sender_address_raw)?;
+
+    if fragments.len() != proxies.len() {
+        return Err(
+            StdError::generic_err("every fragment must be assigned to exactly one proxy").into(),
+        );
+    }
+    if fragments.len() > u8::MAX as usize {
+        return Err(ContractError::QuotaExceeded {
+            what: "fragments".to_string(),
+            max: u8::MAX as u64,
+        });
+    }
+    if threshold == 0 || threshold as usize > fragments.len() {
+        return Err(StdError::generic_err(
+            "threshold must be between 1 and the number of fragments",
+        )
+        .into());
+    }
+    let mut assigned: Vec<Fragment> = Vec::with_capacity(fragments.len());
+    for (fragment, proxy) in fragments.into_iter().zip(proxies) {
+        let proxy = deps.api.addr_validate(&proxy)?;
+        let canonical = deps.api.addr_canonicalize(proxy.as_str())?;
+        check_assignable(deps.storage, &assigned, &canonical, &proxy)?;
+        assigned.push(Fragment {
+            fragment,
+            proxy: Some(StoredAddr {
+                canonical,
+                human: proxy,
+            }),
+        });
+    }
+
+    let key_owner = StoredAddr {
+        canonical: sender_address_raw.clone(),
+        human: info.sender.clone(),
+    };
+    index_key_owner(deps.storage, &mut state, key_owner)?;
+    let count = assigned.len();
+    fragment_sets(deps.storage).save(
+        sender_address_raw.as_slice(),
+        &FragmentSet {
+            threshold,
+            fragments: assigned,
+        },
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "set_fragments",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api.debug(&format!(
+        "{}-of-{} fragments stored by {}",
+        threshold, count, info.sender
+    ));
+    Ok(ResponseBuilder::new("set_fragments")
+        .log("threshold", threshold)
+        .log("fragments", count)
+        .build())
+}
+
+pub fn try_clear_fragments(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    fragment_sets_read(deps.storage).load(sender_address_raw.as_slice())?;
+    fragment_sets(deps.storage).remove(sender_address_raw.as_slice());
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "clear_fragments",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api
+        .debug(&format!("fragments cleared by {}", info.sender));
+    Ok(ResponseBuilder::new("clear_fragments").build())
+}
+
+/// Checks `proxy` is registered and holds none of `fragments` yet.
+fn check_assignable(
+    storage: &dyn Storage,
+    fragments: &[Fragment],
+    proxy: &CanonicalAddr,
+    proxy_human: &Addr,
+) -> StdResult<()> {
+    if !is_registered_proxy(storage, proxy)? {
+        return Err(StdError::generic_err(format!(
+            "{} is not a registered proxy",
+            proxy_human
+        )));
+    }
+    if fragments.iter().any(|fragment| {
+        fragment
+            .proxy
+            .as_ref()
+            .map_or(false, |holder| &holder.canonical == proxy)
+    }) {
+        return Err(StdError::generic_err(format!(
+            "{} is assigned more than one fragment",
+            proxy_human
+        )));
+    }
+    Ok(())
+}
+
+fn is_registered_proxy(storage: &dyn Storage, proxy: &CanonicalAddr) -> StdResult<bool> {
+    let registered = proxies_read(storage).may_load()?.unwrap_or_default();
+    Ok(registered.iter().any(|stored| &stored.canonical == proxy))
+}
+
+pub fn try_assign_fragment(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    index: u8,
+    proxy: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let proxy = deps.api.addr_validate(&proxy)?;
+    let proxy_raw = deps.api.addr_canonicalize(proxy.as_str())?;
+    let mut fragment_set = fragment_sets_read(deps.storage).load(sender_address_raw.as_slice())?;
+    if index as usize >= fragment_set.fragments.len() {
+        return Err(StdError::not_found("fragment").into());
+    }
+    // reassigning a fragment to its current holder is a no-op
+    let current = fragment_set.fragments[index as usize].proxy.take();
+    if current
+        .as_ref()
+        .map_or(true, |holder| holder.canonical != proxy_raw)
+    {
+        check_assignable(deps.storage, &fragment_set.fragments, &proxy_raw, &proxy)?;
+    }
+    fragment_set.fragments[index as usize].proxy = Some(StoredAddr {
+        canonical: proxy_raw,
+        human: proxy.clone(),
+    });
+    fragment_sets(deps.storage).save(sender_address_raw.as_slice(), &fragment_set)?;
+
+    Ok(ResponseBuilder::new("assign_fragment")
+        .log("index", index)
+        .log("proxy", proxy)
+        .build())
+}
+
+pub fn try_unassign_fragment(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    index: u8,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut fragment_set = fragment_sets_read(deps.storage).load(sender_address_raw.as_slice())?;
+    match fragment_set.fragments.get_mut(index as usize) {
+        Some(fragment) => fragment.proxy = None,
+        None => return Err(StdError::not_found("fragment").into()),
+    }
+    fragment_sets(deps.storage).save(sender_address_raw.as_slice(), &fragment_set)?;
+
+    Ok(ResponseBuilder::new("unassign_fragment")
+        .log("index", index)
+        .build())
+}
+
+pub fn try_register_proxy(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut registered = proxies_read(deps.storage).may_load()?.unwrap_or_default();
+    if !registered
+        .iter()
+        .any(|stored| stored.canonical == sender_address_raw)
+    {
+        registered.push(StoredAddr {
+            canonical: sender_address_raw,
+            human: info.sender.clone(),
+        });
+        proxies(deps.storage).save(&registered)?;
+    }
+
+    deps.api
+        .debug(&format!("proxy registered: {}", info.sender));
+    Ok(ResponseBuilder::new("register_proxy")
+        .log("proxy", info.sender)
+        .build())
+}
+
+/// Takes the sender out of the proxy registry. Fragments stay assigned to it, but can't be
+/// read while it is deregistered.
+pub fn try_deregister_proxy(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut registered = proxies_read(deps.storage).may_load()?.unwrap_or_default();
+    let len = registered.len();
+    registered.retain(|stored| stored.canonical != sender_address_raw);
+    if registered.len() == len {
+        return Err(StdError::not_found("proxy").into());
+    }
+    proxies(deps.storage).save(&registered)?;
+
+    deps.api
+        .debug(&format!("proxy deregistered: {}", info.sender));
+    Ok(ResponseBuilder::new("deregister_proxy")
+        .log("proxy", info.sender)
+        .build())
+}
+
+pub fn try_reset(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    label: Option<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let label = label_or_default(label);
+    let existing = keys_read(deps.storage, &sender_address_raw).load(label.as_bytes())?;
+    let version = rotate_key(
+        deps.storage,
+        &sender_address_raw,
+        &label,
+        Some(existing),
+        KeyRecord {
+            reencryption_key: Binary(vec![0; 32]),
+            version: 0,
+            expires_at: None,
+            description: None,
+            document_cid: None,
+            capsule: None,
+        },
+    )?;
+    append_key_hash(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        &[0; 32],
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "reset",
+        Some(label.as_str()),
+        None,
+        None,
+    )?;
+    update_stats(deps.storage, env.block.height, &sender_address_raw, |_| {})?;
+    deps.api
+        .debug(&format!("reencryption key reset by {}", info.sender));
+    Ok(ResponseBuilder::new("reset")
+        .log("owner", &info.sender)
+        .log("label", label)
+        .log("version", version)
+        .log("height", env.block.height)
+        .build())
+}
+
+/// Unlike Reset, which stores a zero key as the next version, removes the key under `label`
+/// and every archived version of it, so it reads as not found from then on.
+pub fn try_delete(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    label: Option<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let label = label_or_default(label);
+    keys_read(deps.storage, &sender_address_raw).load(label.as_bytes())?;
+
+    remove_key(deps.storage, &sender_address_raw, &label)?;
+    let mut owner_labels = labels_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    owner_labels.retain(|stored| stored != &label);
+    labels(deps.storage).save(sender_address_raw.as_slice(), &owner_labels)?;
+    update_stats(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        |totals| {
+            totals.keys = totals.keys.saturating_sub(1);
+            if owner_labels.is_empty() {
+                totals.owners = totals.owners.saturating_sub(1);
+            }
+        },
+    )?;
+    append_key_hash(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        &[0; 32],
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "delete",
+        Some(label.as_str()),
+        None,
+        None,
+    )?;
+    deps.api
+        .debug(&format!("reencryption key deleted by {}", info.sender));
+    Ok(ResponseBuilder::new("delete")
+        .log("owner", &info.sender)
+        .log("label", label)
+        .log("height", env.block.height)
+        .build())
+}
+
+pub fn try_store_capsule(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    label: Option<String>,
+    capsule: Binary,
+) -> Result<Response, ContractError> {
+    if capsule.is_empty() || capsule.len() > MAX_CAPSULE_LENGTH {
+        return Err(ContractError::InvalidCapsule {
+            max: MAX_CAPSULE_LENGTH,
+        });
+    }
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let label = label_or_default(label);
+    let mut record = keys_read(deps.storage, &sender_address_raw).load(label.as_bytes())?;
+    record.capsule = Some(capsule);
+    keys(deps.storage, &sender_address_raw).save(label.as_bytes(), &record)?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "store_capsule",
+        Some(label.as_str()),
+        None,
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("store_capsule")
+        .log("owner", &info.sender)
+        .log("label", label)
+        .log("version", record.version)
+        .build())
+}
+
+pub fn try_store_ciphertext(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    doc_hash: Binary,
+    ciphertext: Binary,
+) -> Result<Response, ContractError> {
+    if doc_hash.len() != 32 {
+        return Err(ContractError::InvalidDocHash {});
+    }
+    if ciphertext.is_empty() || ciphertext.len() > MAX_CIPHERTEXT_LENGTH {
+        return Err(ContractError::InvalidCiphertextLength {
+            len: ciphertext.len(),
+            max: MAX_CIPHERTEXT_LENGTH,
+        });
+    }
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+    check_can_store(&state, &sender_address_raw)?;
+    let owner = StoredAddr {
+        canonical: sender_address_raw.clone(),
+        human: info.sender.clone(),
+    };
+    // indexed so purges and MigrateAddresses reach owners who have no keys
+    index_key_owner(deps.storage, &mut state, owner.clone())?;
+    let mut doc_hashes = ciphertext_hashes_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    if !doc_hashes.contains(&doc_hash) {
+        if doc_hashes.len() >= MAX_CIPHERTEXTS {
+            return Err(ContractError::QuotaExceeded {
+                what: "ciphertexts per owner".to_string(),
+                max: MAX_CIPHERTEXTS as u64,
+            });
+        }
+        doc_hashes.push(doc_hash.clone());
+        ciphertext_hashes(deps.storage).save(sender_address_raw.as_slice(), &doc_hashes)?;
+    }
+
+    ciphertexts(deps.storage, &sender_address_raw).save(
+        doc_hash.as_slice(),
+        &StoredCiphertext {
+            owner,
+            ciphertext,
+            block_height: env.block.height,
+        },
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "store_ciphertext",
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("store_ciphertext")
+        .log("owner", &info.sender)
+        .log("doc_hash", doc_hash.to_base64())
+        .build())
+}
+
+pub fn try_delete_ciphertext(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    doc_hash: Binary,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    ciphertexts_read(deps.storage, &sender_address_raw).load(doc_hash.as_slice())?;
+
+    ciphertexts(deps.storage, &sender_address_raw).remove(doc_hash.as_slice());
+    let mut doc_hashes = ciphertext_hashes_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    doc_hashes.retain(|stored| stored != &doc_hash);
+    ciphertext_hashes(deps.storage).save(sender_address_raw.as_slice(), &doc_hashes)?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "delete_ciphertext",
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("delete_ciphertext")
+        .log("owner", &info.sender)
+        .log("doc_hash", doc_hash.to_base64())
+        .build())
+}
+
+/// Archives `current`, if there is one, and stores `next` as the following version of
+/// `owner`'s key under `label`, numbering it whatever version it was given.
+fn rotate_key(
+    storage: &mut dyn Storage,
+    owner: &CanonicalAddr,
+    label: &str,
+    current: Option<KeyRecord>,
+    mut next: KeyRecord,
+) -> StdResult<u64> {
+    next.version = match current {
+        Some(current) => {
+            let log_id = log_id(storage, owner)?;
+            let mut versions = key_versions(storage, &log_id, label);
+            versions.save(&current.version.to_be_bytes(), &current)?;
+            if current.version > MAX_KEY_VERSIONS {
+                versions.remove(&(current.version - MAX_KEY_VERSIONS).to_be_bytes());
+            }
+            current.version + 1
+        }
+        None => 1,
+    };
+    keys(storage, owner).save(label.as_bytes(), &next)?;
+    Ok(next.version)
+}
+
+/// Checks the description fits and the CID looks like one, either base58 (v0) or base32 (v1).
+fn validate_metadata(
+    description: Option<&str>,
+    document_cid: Option<&str>,
+) -> Result<(), ContractError> {
+    if description.map_or(false, |description| {
+        description.len() > MAX_DESCRIPTION_LENGTH
+    }) {
+        return Err(ContractError::InvalidMetadata {
+            reason: format!(
+                "description is longer than {} bytes",
+                MAX_DESCRIPTION_LENGTH
+            ),
+        });
+    }
+    if let Some(cid) = document_cid {
+        if cid.is_empty()
+            || cid.len() > MAX_CID_LENGTH
+            || !cid.bytes().all(|byte| byte.is_ascii_alphanumeric())
+        {
+            return Err(ContractError::InvalidMetadata {
+                reason: "document_cid is not a CID".to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Removes `owner`'s key under `label` along with the archived versions of it, of which there
+/// are at most `MAX_KEY_VERSIONS`. The label list is left for the caller to update.
+fn remove_key(storage: &mut dyn Storage, owner: &CanonicalAddr, label: &str) -> StdResult<()> {
+    if let Some(record) = keys_read(storage, owner).may_load(label.as_bytes())? {
+        let log_id = log_id(storage, owner)?;
+        let mut versions = key_versions(storage, &log_id, label);
+        for version in record.version.saturating_sub(MAX_KEY_VERSIONS).max(1)..record.version {
+            versions.remove(&version.to_be_bytes());
+        }
     }
+    keys(storage, owner).remove(label.as_bytes());
+    Ok(())
 }
 
-pub fn try_set_reencryption_key<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
+/// Clears expired keys for the key owner index entries in `[start, start + limit)`.
+pub fn try_purge(
+    deps: DepsMut,
     env: Env,
-    key: [u8; 32]
-) -> StdResult<HandleResponse> {
-    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
+    info: MessageInfo,
+    start: u64,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let end = state
+        .key_owners
+        .min(start.saturating_add(page_limit(limit)));
+    let mut purged = 0u64;
+    for index in start..end {
+        let owner = key_owners_read(deps.storage).load(&index.to_be_bytes())?;
+        let owner_labels = labels_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .unwrap_or_default();
+        let mut kept = Vec::with_capacity(owner_labels.len());
+        for label in owner_labels.iter() {
+            let record = keys_read(deps.storage, &owner.canonical).load(label.as_bytes())?;
+            if !record
+                .expires_at
+                .map_or(false, |expires_at| expires_at.is_expired(&env.block))
+            {
+                kept.push(label.clone());
+                continue;
+            }
+            remove_key(deps.storage, &owner.canonical, label)?;
+            append_key_hash(deps.storage, env.block.height, &owner.canonical, &[0; 32])?;
+            record_activity(
+                deps.storage,
+                env.block.height,
+                &owner.canonical,
+                "purge",
+                Some(label.as_str()),
+                None,
+                None,
+            )?;
+            purged += 1;
+        }
+        if kept.len() != owner_labels.len() {
+            labels(deps.storage).save(owner.canonical.as_slice(), &kept)?;
+            let removed = (owner_labels.len() - kept.len()) as u64;
+            update_stats(deps.storage, env.block.height, &owner.canonical, |totals| {
+                totals.keys = totals.keys.saturating_sub(removed);
+                if kept.is_empty() {
+                    totals.owners = totals.owners.saturating_sub(1);
+                }
+            })?;
+        }
+    }
+
+    purge_progress(deps.storage).save(&PurgeProgress {
+        last_height: env.block.height,
+        next: end,
+    })?;
+
+    deps.api.debug(&format!("purged {} expired keys", purged));
+    Ok(ResponseBuilder::new("purge")
+        .log("purged", purged)
+        .log("next", end)
+        .build())
+}
 
-    config(&mut deps.storage).update(|mut state| {
+pub fn try_retire(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    notice_period: u64,
+) -> Result<Response, ContractError> {
+    if notice_period < MIN_RETIREMENT_NOTICE {
+        return Err(StdError::generic_err(format!(
+            "retirement notice period must be at least {} seconds",
+            MIN_RETIREMENT_NOTICE
+        ))
+        .into());
+    }
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let announced_at = env.block.time.seconds();
+    let purge_after = announced_at.saturating_add(notice_period);
+    config(deps.storage).update(|mut state| {
         if sender_address_raw != state.owner {
-            return Err(StdError::Unauthorized { backtrace: None });
+            return Err(ContractError::Unauthorized {});
+        }
+        if state.retirement.is_some() {
+            return Err(StdError::generic_err("contract is already retiring").into());
         }
-        state.reencryption_key = key;
+        state.retirement = Some(Retirement {
+            announced_at,
+            purge_after,
+            purge_cursor: 0,
+            purged: false,
+        });
         Ok(state)
     })?;
 
-    debug_print("reencryption key registered by {}");
-    Ok(HandleResponse::default())
+    deps.api.debug(&format!(
+        "retirement announced, purge allowed after {}",
+        purge_after
+    ));
+    Ok(ResponseBuilder::new("retire")
+        .log("announced_at", announced_at)
+        .log("purge_after", purge_after)
+        .build())
 }
 
-pub fn try_reset<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    env: Env,
-) -> StdResult<HandleResponse> {
-    let sender_address_raw = deps.api.canonical_address(&env.message.sender)?;
-    config(&mut deps.storage).update(|mut state| {
+pub fn try_cancel_retirement(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
         if sender_address_raw != state.owner {
-            return Err(StdError::Unauthorized { backtrace: None });
+            return Err(ContractError::Unauthorized {});
+        }
+        match state.retirement {
+            None => return Err(StdError::generic_err("contract is not retiring").into()),
+            Some(ref retirement) if retirement.purged || retirement.purge_cursor > 0 => {
+                return Err(StdError::generic_err("retired data has already been purged").into())
+            }
+            Some(_) => {}
         }
-        state.reencryption_key = [0;32];
+        state.retirement = None;
         Ok(state)
     })?;
-    debug_print("count reset successfully");
-    Ok(HandleResponse::default())
+
+    deps.api.debug("retirement cancelled");
+    Ok(ResponseBuilder::new("cancel_retirement").build())
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetReencryptionKey {} => to_binary(&query_count(deps)?),
+pub fn try_purge_retired(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
     }
-}
+    let mut retirement = match state.retirement {
+        Some(retirement) => retirement,
+        None => return Err(StdError::generic_err("contract is not retiring").into()),
+    };
+    if env.block.time.seconds() < retirement.purge_after {
+        return Err(StdError::generic_err("retirement notice period has not ended").into());
+    }
+
+    // owners are purged in batches so a large index can't exhaust the gas limit
+    let end = state
+        .key_owners
+        .min(retirement.purge_cursor.saturating_add(PURGE_BATCH_SIZE));
+    for index in retirement.purge_cursor..end {
+        let owner = key_owners_read(deps.storage).load(&index.to_be_bytes())?;
+        let owner_labels = labels_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .unwrap_or_default();
+        for label in owner_labels.iter() {
+            remove_key(deps.storage, &owner.canonical, label)?;
+        }
+        labels(deps.storage).save(owner.canonical.as_slice(), &Vec::new())?;
+        if !owner_labels.is_empty() {
+            update_stats(deps.storage, env.block.height, &owner.canonical, |totals| {
+                totals.keys = totals.keys.saturating_sub(owner_labels.len() as u64);
+                totals.owners = totals.owners.saturating_sub(1);
+            })?;
+        }
+        fragment_sets(deps.storage).remove(owner.canonical.as_slice());
+        let doc_hashes = ciphertext_hashes_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .unwrap_or_default();
+        for doc_hash in doc_hashes.iter() {
+            ciphertexts(deps.storage, &owner.canonical).remove(doc_hash.as_slice());
+        }
+        ciphertext_hashes(deps.storage).remove(owner.canonical.as_slice());
+        append_key_hash(deps.storage, env.block.height, &owner.canonical, &[0; 32])?;
+        record_activity(
+            deps.storage,
+            env.block.height,
+            &owner.canonical,
+            "purge_retired",
+            None,
+            None,
+            None,
+        )?;
+    }
+    let purged_owners = end - retirement.purge_cursor;
+    retirement.purge_cursor = end;
+    retirement.purged = end == state.key_owners;
+    let done = retirement.purged;
+    state.retirement = Some(retirement);
+    config(deps.storage).save(&state)?;
 
-fn query_count<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<ReencryptionKeyResponse> {
-    let state = config_read(&deps.storage).load()?;
-    Ok(ReencryptionKeyResponse { reencryption_key: state.reencryption_key })
+    deps.api
+        .debug(&format!("purged {} retired keys", purged_owners));
+    Ok(ResponseBuilder::new("purge_retired")
+        .log("purged_owners", purged_owners)
+        .log("done", done)
+        .build())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env};
-    use cosmwasm_std::{coins, from_binary, StdError};
+pub fn try_set_maintenance_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    eta: Option<u64>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        state.maintenance = Some(Maintenance { eta });
+        Ok(state)
+    })?;
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies(20, &[]);
+    deps.api.debug("maintenance mode enabled");
+    Ok(ResponseBuilder::new("set_maintenance_mode").build())
+}
 
-        let msg = InitMsg { };
-        let env = mock_env("creator", &coins(1000, "earth"));
+pub fn try_clear_maintenance_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        state.maintenance = None;
+        Ok(state)
+    })?;
 
-        // we can just call .unwrap() to assert this was a success
-        let res = init(&mut deps, env, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+    deps.api.debug("maintenance mode cleared");
+    Ok(ResponseBuilder::new("clear_maintenance_mode").build())
+}
 
-        // it worked, let's query the state
-        let res = query(&deps, QueryMsg::GetReencryptionKey {}).unwrap();
-        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
-        assert_eq!([0;32], value.reencryption_key);
-    }
+pub fn try_set_paused(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    paused: bool,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        state.paused = paused;
+        Ok(state)
+    })?;
 
-    #[test]
-    fn set() {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
+    deps.api.debug(if paused {
+        "contract paused"
+    } else {
+        "contract unpaused"
+    });
+    Ok(ResponseBuilder::new(if paused { "pause" } else { "unpause" }).build())
+}
+
+pub fn try_set_guardian(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let guardian = match guardian {
+        Some(guardian) => {
+            let human = deps.api.addr_validate(&guardian)?;
+            Some(StoredAddr {
+                canonical: deps.api.addr_canonicalize(human.as_str())?,
+                human,
+            })
+        }
+        None => None,
+    };
+    let logged = guardian
+        .as_ref()
+        .map_or_else(String::new, |guardian| guardian.human.to_string());
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        if state.frozen {
+            return Err(ContractError::Frozen {});
+        }
+        state.guardian = guardian;
+        Ok(state)
+    })?;
 
-        let msg = InitMsg { };
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+    Ok(ResponseBuilder::new("set_guardian")
+        .log("guardian", logged)
+        .build())
+}
 
-        // not anyone can reset
-        let unauth_env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::Set {reencryption_key: [66;32]};
-        let res = handle(&mut deps, unauth_env, msg);
-        match res {
-            Err(StdError::Unauthorized { .. }) => {}
-            _ => panic!("Must return unauthorized error"),
+pub fn try_set_frozen(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    frozen: bool,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if state
+            .guardian
+            .as_ref()
+            .map_or(true, |guardian| guardian.canonical != sender_address_raw)
+        {
+            return Err(ContractError::Unauthorized {});
         }
+        state.frozen = frozen;
+        Ok(state)
+    })?;
 
-        // only creator can set
-        let env = mock_env("creator", &coins(2, "token"));
-        let msg = HandleMsg::Set {reencryption_key: [1;32]};
-        let _res = handle(&mut deps, env, msg).unwrap();
+    deps.api.debug(if frozen {
+        "key disclosure frozen"
+    } else {
+        "key disclosure unfrozen"
+    });
+    Ok(ResponseBuilder::new(if frozen { "freeze" } else { "unfreeze" }).build())
+}
 
-        // should be set
-        let res = query(&deps, QueryMsg::GetReencryptionKey {}).unwrap();
-        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
-        assert_eq!([1;32], value.reencryption_key);
-    }
+pub fn try_create_viewing_key(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    entropy: String,
+) -> Result<Response, ContractError> {
+    let seed = read_prng_seed(deps.storage);
+    let key = ViewingKey::new(&env, &info, &seed, entropy.as_bytes());
+    let seed = advance_prng_seed(deps.storage, &seed, key.as_bytes());
 
-    #[test]
-    fn reset() {
-        let mut deps = mock_dependencies(20, &coins(2, "token"));
+    // replaces any key the sender created before
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    write_viewing_key(
+        deps.storage,
+        &sender_address_raw,
+        &key,
+        new_salt(&seed),
+        env.block.height,
+    )?;
 
-        let msg = InitMsg {};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+    Ok(ResponseBuilder::new("create_viewing_key")
+        .data(to_binary(&ExecuteAnswer::CreateViewingKey { key })?)
+        .build())
+}
 
-        // not anyone can reset
-        let unauth_env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg::Reset {};
-        let res = handle(&mut deps, unauth_env, msg);
-        match res {
-            Err(StdError::Unauthorized { .. }) => {}
-            _ => panic!("Must return unauthorized error"),
+/// Moves the contract's seed on past `secret`, so no later secret is derived from the same
+/// seed. Returns the new seed.
+fn advance_prng_seed(storage: &mut dyn Storage, seed: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(secret);
+    let seed = hasher.finalize().to_vec();
+    write_prng_seed(storage, &seed);
+    seed
+}
+
+pub fn try_transfer_ownership(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    new_owner: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    let new_owner_raw = deps.api.addr_canonicalize(new_owner.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
         }
+        state.pending_owner = Some(StoredAddr {
+            canonical: new_owner_raw,
+            human: new_owner.clone(),
+        });
+        Ok(state)
+    })?;
 
-        // only the original creator can reset the counter
-        let auth_env = mock_env("creator", &coins(2, "token"));
-        let set_msg = HandleMsg::Set {reencryption_key: [55;32]};
-        let _set_res = handle(&mut deps, auth_env, set_msg).unwrap();
+    deps.api
+        .debug(&format!("ownership transfer to {} proposed", new_owner));
+    Ok(ResponseBuilder::new("transfer_ownership")
+        .log("pending_owner", new_owner)
+        .build())
+}
 
-        // should now be 55
-        let res = query(&deps, QueryMsg::GetReencryptionKey {}).unwrap();
-        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
-        assert_eq!([55;32], value.reencryption_key);
+pub fn try_accept_ownership(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| match state.pending_owner.take() {
+        Some(pending_owner) if pending_owner.canonical == sender_address_raw => {
+            state.owner = pending_owner.canonical;
+            state.owner_human = pending_owner.human;
+            Ok(state)
+        }
+        _ => Err(ContractError::Unauthorized {}),
+    })?;
 
-        // reset it now
-        let auth_env = mock_env("creator", &coins(2, "token"));
-        let reset_msg = HandleMsg::Reset {};
-        let _reset_res = handle(&mut deps, auth_env, reset_msg).unwrap();
+    deps.api
+        .debug(&format!("ownership accepted by {}", info.sender));
+    Ok(ResponseBuilder::new("accept_ownership")
+        .log("owner", info.sender)
+        .build())
+}
 
-        // should now be 0
-        let res = query(&deps, QueryMsg::GetReencryptionKey {}).unwrap();
-        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
-        assert_eq!([0;32], value.reencryption_key);
+pub fn try_add_setter(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let address = deps.api.addr_validate(&address)?;
+    let address_raw = deps.api.addr_canonicalize(address.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        if !state
+            .setters
+            .iter()
+            .any(|setter| setter.canonical == address_raw)
+        {
+            state.setters.push(StoredAddr {
+                canonical: address_raw,
+                human: address.clone(),
+            });
+        }
+        Ok(state)
+    })?;
+
+    Ok(ResponseBuilder::new("add_setter")
+        .log("setter", address)
+        .build())
+}
+
+pub fn try_remove_setter(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let address_raw = deps.api.addr_canonicalize(&address)?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        let before = state.setters.len();
+        state
+            .setters
+            .retain(|setter| setter.canonical != address_raw);
+        if state.setters.len() == before {
+            return Err(StdError::not_found("setter").into());
+        }
+        Ok(state)
+    })?;
+
+    Ok(ResponseBuilder::new("remove_setter")
+        .log("setter", address)
+        .build())
+}
+
+pub fn try_set_set_mode(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    mode: SetMode,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    config(deps.storage).update(|mut state| {
+        if sender_address_raw != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        state.set_mode = mode;
+        Ok(state)
+    })?;
+
+    let mode = match mode {
+        SetMode::Open => "open",
+        SetMode::Closed => "closed",
+    };
+    Ok(ResponseBuilder::new("set_set_mode")
+        .log("mode", mode)
+        .build())
+}
+
+pub fn try_withdraw(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    amount: Option<Vec<Coin>>,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => state.owner_human,
+    };
+
+    let mut collected = collected_fees_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    let amount: Vec<Coin> = match amount {
+        Some(amount) => amount,
+        None => collected.clone(),
+    }
+    .into_iter()
+    .filter(|coin| !coin.amount.is_zero())
+    .collect();
+    if amount.is_empty() {
+        return Err(StdError::generic_err("there is nothing to withdraw").into());
+    }
+    for coin in amount.iter() {
+        let position = collected.iter().position(|total| total.denom == coin.denom);
+        match position {
+            Some(position) if collected[position].amount >= coin.amount => {
+                collected[position].amount -= coin.amount
+            }
+            _ => {
+                return Err(ContractError::ExceedsCollectedFees {
+                    available: position.map_or_else(
+                        || Coin::new(0, coin.denom.clone()),
+                        |position| collected[position].clone(),
+                    ),
+                })
+            }
+        }
+    }
+    collected.retain(|total| !total.amount.is_zero());
+    collected_fees(deps.storage).save(&collected)?;
+
+    deps.api
+        .debug(&format!("collected fees withdrawn to {}", recipient));
+    Ok(ResponseBuilder::new("withdraw")
+        .log("recipient", &recipient)
+        .message(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount,
+        })
+        .build())
+}
+
+pub fn try_set_token_fee(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token_fee: Option<TokenFeeMsg>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_address_raw != config_read(deps.storage).load()?.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let token_fee = match token_fee {
+        Some(token_fee) if token_fee.amount.is_zero() => {
+            return Err(StdError::generic_err("token fee must not be zero").into())
+        }
+        Some(token_fee) => Some(TokenFee {
+            token: deps.api.addr_validate(&token_fee.token)?,
+            code_hash: token_fee.code_hash,
+            amount: token_fee.amount,
+        }),
+        None => None,
+    };
+    let mut parameters = contract_config_read(deps.storage).load()?;
+    let same_token = match (&parameters.token_set_fee, &token_fee) {
+        (Some(current), Some(new)) => current.token == new.token,
+        (current, _) => current.is_none(),
+    };
+    if !same_token
+        && !collected_tokens_read(deps.storage)
+            .may_load()?
+            .unwrap_or_default()
+            .is_zero()
+    {
+        return Err(StdError::generic_err(
+            "collected tokens must be withdrawn before the token is changed",
+        )
+        .into());
+    }
+    // credits were paid for in the current token, and would be left with nothing to spend
+    // them on
+    if !same_token
+        && outstanding_credits_read(deps.storage)
+            .may_load()?
+            .unwrap_or_default()
+            > 0
+    {
+        return Err(StdError::generic_err(
+            "set credits must be used up before the token is changed",
+        )
+        .into());
+    }
+    parameters.token_set_fee = token_fee.clone();
+    contract_config(deps.storage).save(&parameters)?;
+
+    let mut response = ResponseBuilder::new("set_token_fee");
+    if let Some(token_fee) = token_fee {
+        response = response
+            .log("token", &token_fee.token)
+            .log("amount", token_fee.amount)
+            .message(WasmMsg::Execute {
+                contract_addr: token_fee.token.to_string(),
+                code_hash: token_fee.code_hash,
+                msg: to_binary(&Snip20Msg::RegisterReceive {
+                    code_hash: env.contract.code_hash,
+                })?,
+                funds: vec![],
+            });
+    }
+    Ok(response.build())
+}
+
+/// Credits `from` for tokens sent by the accepted SNIP-20 contract, which calls this.
+pub fn try_receive(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    from: String,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
+    let token_fee = match contract_config_read(deps.storage).load()?.token_set_fee {
+        Some(token_fee) if token_fee.token == info.sender => token_fee,
+        _ => return Err(ContractError::Unauthorized {}),
+    };
+    // credits bought now could not be spent, as no more keys can be stored
+    if config_read(deps.storage).load()?.retirement.is_some() {
+        return Err(ContractError::Retiring {});
+    }
+    let msg = msg.ok_or_else(|| StdError::generic_err("a receive message is required"))?;
+    let payer = deps.api.addr_validate(&from)?;
+    let payer_raw = deps.api.addr_canonicalize(payer.as_str())?;
+
+    let credited = match from_binary(&msg)? {
+        ReceiveMsg::PayForSet {} => {
+            let credited = (amount.u128() / token_fee.amount.u128()) as u64;
+            if credited == 0 {
+                return Err(ContractError::InsufficientFee {
+                    required: Coin {
+                        denom: token_fee.token.to_string(),
+                        amount: token_fee.amount,
+                    },
+                });
+            }
+            let credits = set_credits_read(deps.storage)
+                .may_load(payer_raw.as_slice())?
+                .unwrap_or_default();
+            set_credits(deps.storage)
+                .save(payer_raw.as_slice(), &credits.saturating_add(credited))?;
+            let outstanding = outstanding_credits_read(deps.storage)
+                .may_load()?
+                .unwrap_or_default();
+            outstanding_credits(deps.storage).save(&outstanding.saturating_add(credited))?;
+            // indexed so MigrateAddresses moves the credits of payers who have no keys yet
+            let mut state = config_read(deps.storage).load()?;
+            index_key_owner(
+                deps.storage,
+                &mut state,
+                StoredAddr {
+                    canonical: payer_raw.clone(),
+                    human: payer.clone(),
+                },
+            )?;
+            credited
+        }
+    };
+    let collected = collected_tokens_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    collected_tokens(deps.storage).save(&(collected + amount))?;
+
+    deps.api
+        .debug(&format!("{} paid for {} sets", payer, credited));
+    Ok(ResponseBuilder::new("receive")
+        .log("payer", payer)
+        .log("credited", credited)
+        .build())
+}
+
+pub fn try_withdraw_tokens(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    amount: Option<Uint128>,
+    recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => state.owner_human,
+    };
+    let token_fee = contract_config_read(deps.storage)
+        .load()?
+        .token_set_fee
+        .ok_or_else(|| StdError::not_found("token fee"))?;
+
+    let collected = collected_tokens_read(deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    let amount = amount.unwrap_or(collected);
+    if amount.is_zero() {
+        return Err(StdError::generic_err("there is nothing to withdraw").into());
+    }
+    if amount > collected {
+        return Err(ContractError::ExceedsCollectedFees {
+            available: Coin {
+                denom: token_fee.token.to_string(),
+                amount: collected,
+            },
+        });
+    }
+    collected_tokens(deps.storage).save(&(collected - amount))?;
+
+    deps.api
+        .debug(&format!("collected tokens withdrawn to {}", recipient));
+    Ok(ResponseBuilder::new("withdraw_tokens")
+        .log("recipient", &recipient)
+        .message(WasmMsg::Execute {
+            contract_addr: token_fee.token.to_string(),
+            code_hash: token_fee.code_hash,
+            msg: to_binary(&Snip20Msg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        })
+        .build())
+}
+
+pub fn try_update_config(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    max_keys_per_owner: Option<u32>,
+    set_fee: Option<Coin>,
+    default_inactivity_window: Option<u64>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_address_raw != config_read(deps.storage).load()?.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let parameters = contract_config(deps.storage).update(|mut parameters| -> StdResult<_> {
+        if let Some(max) = max_keys_per_owner {
+            parameters.max_keys_per_owner = Some(max).filter(|&max| max > 0);
+        }
+        if let Some(fee) = set_fee {
+            parameters.set_fee = Some(fee).filter(|fee| !fee.amount.is_zero());
+        }
+        if let Some(window) = default_inactivity_window {
+            parameters.default_inactivity_window = Some(window).filter(|&window| window > 0);
+        }
+        Ok(parameters)
+    })?;
+
+    deps.api
+        .debug(&format!("configuration updated by {}", info.sender));
+    let mut response = ResponseBuilder::new("update_config");
+    if let Some(max) = parameters.max_keys_per_owner {
+        response = response.log("max_keys_per_owner", max);
+    }
+    if let Some(fee) = parameters.set_fee {
+        response = response.log("set_fee", fee);
+    }
+    if let Some(window) = parameters.default_inactivity_window {
+        response = response.log("default_inactivity_window", window);
+    }
+    Ok(response.build())
+}
+
+pub fn try_set_max_key_length(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    max_key_length: u32,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if max_key_length < 32 {
+        return Err(StdError::generic_err("max key length must be at least 32 bytes").into());
+    }
+    if sender_address_raw != config_read(deps.storage).load()?.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    contract_config(deps.storage).update(|mut parameters| -> StdResult<_> {
+        parameters.max_key_length = max_key_length;
+        Ok(parameters)
+    })?;
+
+    Ok(ResponseBuilder::new("set_max_key_length")
+        .log("max_key_length", max_key_length)
+        .build())
+}
+
+pub fn try_set_key_type(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    key_type: KeyType,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if sender_address_raw != config_read(deps.storage).load()?.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    contract_config(deps.storage).update(|mut parameters| -> StdResult<_> {
+        parameters.key_type = key_type;
+        Ok(parameters)
+    })?;
+
+    let key_type = match key_type {
+        KeyType::Any => "any",
+        KeyType::X25519 => "x25519",
+        KeyType::Secp256k1 => "secp256k1",
+    };
+    Ok(ResponseBuilder::new("set_key_type")
+        .log("key_type", key_type)
+        .build())
+}
+
+pub fn try_grant(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    grantee: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let grantee = deps.api.addr_validate(&grantee)?;
+    let grantee_raw = deps.api.addr_canonicalize(grantee.as_str())?;
+    // only stored keys can be shared
+    let owner_labels = labels_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    if owner_labels.is_empty() {
+        return Err(StdError::not_found("reencryption key").into());
+    }
+
+    let mut grantees = grants_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    if !grantees
+        .iter()
+        .any(|stored| stored.canonical == grantee_raw)
+    {
+        grantees.push(StoredAddr {
+            canonical: grantee_raw,
+            human: grantee.clone(),
+        });
+        grants(deps.storage).save(sender_address_raw.as_slice(), &grantees)?;
+        update_stats(
+            deps.storage,
+            env.block.height,
+            &sender_address_raw,
+            |totals| totals.grants += 1,
+        )?;
+        record_activity(
+            deps.storage,
+            env.block.height,
+            &sender_address_raw,
+            "grant",
+            None,
+            Some(&grantee),
+            None,
+        )?;
+    }
+
+    deps.api
+        .debug(&format!("{} granted access to {}", info.sender, grantee));
+    Ok(ResponseBuilder::new("grant")
+        .log("grantee", grantee)
+        .build())
+}
+
+pub fn try_revoke(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    grantee: String,
+) -> Result<Response, ContractError> {
+    revoke_grants(deps.branch(), &env, &info, std::slice::from_ref(&grantee))?;
+
+    deps.api
+        .debug(&format!("{} revoked access from {}", info.sender, grantee));
+    Ok(ResponseBuilder::new("revoke")
+        .log("grantee", grantee)
+        .build())
+}
+
+/// Revokes every grant or, if any of them doesn't exist, none.
+pub fn try_batch_revoke(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    grantees: Vec<String>,
+) -> Result<Response, ContractError> {
+    if grantees.is_empty() {
+        return Err(StdError::generic_err("at least one grantee is required").into());
+    }
+    if grantees.len() > MAX_BATCH_SIZE {
+        return Err(ContractError::QuotaExceeded {
+            what: "entries per batch".to_string(),
+            max: MAX_BATCH_SIZE as u64,
+        });
+    }
+    let revoked = revoke_grants(deps, &env, &info, &grantees)?;
+
+    let mut response = ResponseBuilder::new("batch_revoke");
+    for grantee in revoked {
+        response = response.log("grantee", grantee);
+    }
+    Ok(response.build())
+}
+
+/// Removes the sender's grants to `grantees`, returning the addresses they were granted to.
+fn revoke_grants(
+    deps: DepsMut,
+    env: &Env,
+    info: &MessageInfo,
+    grantees: &[String],
+) -> Result<Vec<Addr>, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+
+    let mut granted = grants_read(deps.storage)
+        .may_load(sender_address_raw.as_slice())?
+        .unwrap_or_default();
+    let mut revoked: Vec<StoredAddr> = Vec::with_capacity(grantees.len());
+    for grantee in grantees {
+        let grantee_raw = deps.api.addr_canonicalize(grantee)?;
+        // a grantee listed twice is only revoked once
+        if revoked.iter().any(|stored| stored.canonical == grantee_raw) {
+            continue;
+        }
+        let position = granted
+            .iter()
+            .position(|stored| stored.canonical == grantee_raw)
+            .ok_or_else(|| StdError::not_found("grant"))?;
+        revoked.push(granted.remove(position));
+    }
+    grants(deps.storage).save(sender_address_raw.as_slice(), &granted)?;
+    update_stats(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        |totals| totals.grants = totals.grants.saturating_sub(revoked.len() as u64),
+    )?;
+    for grantee in revoked.iter() {
+        record_activity(
+            deps.storage,
+            env.block.height,
+            &sender_address_raw,
+            "revoke",
+            None,
+            Some(&grantee.human),
+            None,
+        )?;
+    }
+    Ok(revoked.into_iter().map(|stored| stored.human).collect())
+}
+
+pub fn try_mint_retrieval_token(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: Option<String>,
+    label: Option<String>,
+    expires_at: Expiration,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let owner = match owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender.clone(),
+    };
+    let owner_raw = deps.api.addr_canonicalize(owner.as_str())?;
+    if owner_raw != sender_address_raw {
+        check_released(deps.as_ref(), &env.block, &owner_raw, &sender_address_raw)?;
+    }
+    if expires_at.is_expired(&env.block) {
+        return Err(ContractError::ExpiryInPast {});
+    }
+    let label = label_or_default(label);
+    // only stored keys can be handed out
+    keys_read(deps.storage, &owner_raw).load(label.as_bytes())?;
+
+    let seed = read_prng_seed(deps.storage);
+    let token = new_retrieval_token(&env, &info, &seed, label.as_bytes());
+    advance_prng_seed(deps.storage, &seed, token.as_bytes());
+    retrieval_tokens(deps.storage).save(
+        &Sha256::digest(token.as_bytes()),
+        &RetrievalToken {
+            owner: StoredAddr {
+                canonical: owner_raw.clone(),
+                human: owner.clone(),
+            },
+            label: label.clone(),
+            expires_at,
+        },
+    )?;
+    let minter = if owner_raw != sender_address_raw {
+        Some(&info.sender)
+    } else {
+        None
+    };
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &owner_raw,
+        "mint_retrieval_token",
+        Some(label.as_str()),
+        minter,
+        None,
+    )?;
+
+    deps.api.debug(&format!(
+        "{} minted a retrieval token for {}",
+        info.sender, owner
+    ));
+    Ok(ResponseBuilder::new("mint_retrieval_token")
+        .log("owner", owner)
+        .log("label", label)
+        .data(to_binary(&ExecuteAnswer::MintRetrievalToken { token })?)
+        .build())
+}
+
+/// Returns the key a retrieval token was minted for. The token is burned, so it works once;
+/// if it fails, for instance because the key has since expired, it stays unspent.
+pub fn try_redeem_retrieval_token(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: String,
+) -> Result<Response, ContractError> {
+    if config_read(deps.storage).load()?.frozen {
+        return Err(ContractError::Frozen {});
+    }
+    let token_hash = Sha256::digest(token.as_bytes());
+    let stored = retrieval_tokens_read(deps.storage).load(&token_hash)?;
+    if stored.expires_at.is_expired(&env.block) {
+        return Err(ContractError::TokenExpired {});
+    }
+    retrieval_tokens(deps.storage).remove(&token_hash);
+
+    let record = keys_read(deps.storage, &stored.owner.canonical).load(stored.label.as_bytes())?;
+    let key = key_response(&env.block, record);
+    let reencryption_key = key.reencryption_key.ok_or(ContractError::KeyExpired {})?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &stored.owner.canonical,
+        "redeem_retrieval_token",
+        Some(stored.label.as_str()),
+        Some(&info.sender),
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("redeem_retrieval_token")
+        .log("owner", &stored.owner.human)
+        .log("label", &stored.label)
+        .data(to_binary(&ExecuteAnswer::RedeemRetrievalToken {
+            owner: stored.owner.human,
+            label: stored.label,
+            reencryption_key,
+            version: key.version,
+        })?)
+        .build())
+}
+
+pub fn try_set_dead_man_switch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    inactivity_window: Option<u64>,
+    beneficiaries: Vec<String>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let inactivity_window = match inactivity_window {
+        Some(inactivity_window) => inactivity_window,
+        None => contract_config_read(deps.storage)
+            .load()?
+            .default_inactivity_window
+            .ok_or_else(|| StdError::generic_err("an inactivity window is required"))?,
+    };
+    if inactivity_window == 0 {
+        return Err(StdError::generic_err("inactivity window must not be zero").into());
+    }
+    if beneficiaries.is_empty() {
+        return Err(StdError::generic_err("at least one beneficiary is required").into());
+    }
+    let beneficiaries = beneficiaries
+        .into_iter()
+        .map(|human| {
+            let human = deps.api.addr_validate(&human)?;
+            Ok(StoredAddr {
+                canonical: deps.api.addr_canonicalize(human.as_str())?,
+                human,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let switch = DeadManSwitch {
+        inactivity_window,
+        last_heartbeat: env.block.time.seconds(),
+        beneficiaries,
+    };
+    dead_man_switches(deps.storage).save(sender_address_raw.as_slice(), &switch)?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "set_dead_man_switch",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api
+        .debug(&format!("dead-man switch armed by {}", info.sender));
+    Ok(ResponseBuilder::new("set_dead_man_switch")
+        .log("deadline", switch.deadline())
+        .build())
+}
+
+pub fn try_clear_dead_man_switch(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    dead_man_switches_read(deps.storage).load(sender_address_raw.as_slice())?;
+    dead_man_switches(deps.storage).remove(sender_address_raw.as_slice());
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "clear_dead_man_switch",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api
+        .debug(&format!("dead-man switch cleared by {}", info.sender));
+    Ok(ResponseBuilder::new("clear_dead_man_switch").build())
+}
+
+pub fn try_heartbeat(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let switch = dead_man_switches(deps.storage).update(
+        sender_address_raw.as_slice(),
+        |switch| match switch {
+            Some(mut switch) => {
+                switch.last_heartbeat = env.block.time.seconds();
+                Ok(switch)
+            }
+            None => Err(StdError::not_found("dead-man switch")),
+        },
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "heartbeat",
+        None,
+        None,
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("heartbeat")
+        .log("deadline", switch.deadline())
+        .build())
+}
+
+pub fn try_set_ceremony(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    participants: Vec<String>,
+    window: u64,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    if window == 0 {
+        return Err(StdError::generic_err("ceremony window must not be zero").into());
+    }
+    if participants.is_empty() {
+        return Err(StdError::generic_err("at least one participant is required").into());
+    }
+    let mut stored: Vec<StoredAddr> = Vec::with_capacity(participants.len());
+    for human in participants {
+        let human = deps.api.addr_validate(&human)?;
+        let canonical = deps.api.addr_canonicalize(human.as_str())?;
+        if stored
+            .iter()
+            .any(|participant| participant.canonical == canonical)
+        {
+            return Err(
+                StdError::generic_err(format!("{} is listed more than once", human)).into(),
+            );
+        }
+        stored.push(StoredAddr { canonical, human });
+    }
+
+    // replacing a ceremony starts it over
+    let ceremony = Ceremony {
+        participants: stored,
+        window,
+        round_started_at: None,
+        checked_in: vec![],
+        completed_at: None,
+    };
+    ceremonies(deps.storage).save(sender_address_raw.as_slice(), &ceremony)?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "set_ceremony",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api
+        .debug(&format!("release ceremony set by {}", info.sender));
+    Ok(ResponseBuilder::new("set_ceremony")
+        .log("participants", ceremony.participants.len())
+        .log("window", window)
+        .build())
+}
+
+pub fn try_clear_ceremony(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    ceremonies_read(deps.storage).load(sender_address_raw.as_slice())?;
+    ceremonies(deps.storage).remove(sender_address_raw.as_slice());
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &sender_address_raw,
+        "clear_ceremony",
+        None,
+        None,
+        None,
+    )?;
+
+    deps.api
+        .debug(&format!("release ceremony cleared by {}", info.sender));
+    Ok(ResponseBuilder::new("clear_ceremony").build())
+}
+
+pub fn try_check_in(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let owner_raw = deps.api.addr_canonicalize(&owner)?;
+    let now = env.block.time.seconds();
+    let ceremony = ceremonies(deps.storage).update(
+        owner_raw.as_slice(),
+        |ceremony| -> Result<Ceremony, ContractError> {
+            let mut ceremony = ceremony.ok_or_else(|| StdError::not_found("ceremony"))?;
+            if !ceremony.is_participant(&sender_address_raw) {
+                return Err(ContractError::Unauthorized {});
+            }
+            if ceremony.completed_at.is_some() {
+                return Err(StdError::generic_err("ceremony is already complete").into());
+            }
+            if ceremony.round_started_at.is_none() || ceremony.has_lapsed(now) {
+                ceremony.round_started_at = Some(now);
+                ceremony.checked_in.clear();
+            }
+            if !ceremony
+                .checked_in
+                .iter()
+                .any(|stored| stored.canonical == sender_address_raw)
+            {
+                ceremony.checked_in.push(StoredAddr {
+                    canonical: sender_address_raw.clone(),
+                    human: info.sender.clone(),
+                });
+            }
+            if ceremony.checked_in.len() == ceremony.participants.len() {
+                ceremony.completed_at = Some(now);
+            }
+            Ok(ceremony)
+        },
+    )?;
+    record_activity(
+        deps.storage,
+        env.block.height,
+        &owner_raw,
+        if ceremony.completed_at.is_some() {
+            "complete_ceremony"
+        } else {
+            "check_in"
+        },
+        None,
+        Some(&info.sender),
+        None,
+    )?;
+
+    Ok(ResponseBuilder::new("check_in")
+        .log("owner", owner)
+        .log("checked_in", ceremony.checked_in.len())
+        .log("completed", ceremony.completed_at.is_some())
+        .build())
+}
+
+/// Brings stored addresses in line with the chain's current address scheme. Where the
+/// stored human address still parses, its canonical form is re-derived and the records
+/// keyed by it are moved; where it no longer does (e.g. after a bech32 prefix change),
+/// the human form is re-derived from the canonical one instead.
+pub fn try_migrate_addresses(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    start: u64,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let mut state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let (owner, owner_human) = migrate_address(deps.api, &state.owner, &state.owner_human)?;
+    state.owner = owner;
+    state.owner_human = owner_human;
+    if let Some(pending_owner) = state.pending_owner.take() {
+        let (canonical, human) =
+            migrate_address(deps.api, &pending_owner.canonical, &pending_owner.human)?;
+        state.pending_owner = Some(StoredAddr { canonical, human });
+    }
+    if let Some(guardian) = state.guardian.take() {
+        let (canonical, human) = migrate_address(deps.api, &guardian.canonical, &guardian.human)?;
+        state.guardian = Some(StoredAddr { canonical, human });
+    }
+    state.setters = state
+        .setters
+        .iter()
+        .map(|setter| {
+            let (canonical, human) = migrate_address(deps.api, &setter.canonical, &setter.human)?;
+            Ok(StoredAddr { canonical, human })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    config(deps.storage).save(&state)?;
+    if let Some(registered) = proxies_read(deps.storage).may_load()? {
+        let registered = registered
+            .iter()
+            .map(|proxy| {
+                let (canonical, human) = migrate_address(deps.api, &proxy.canonical, &proxy.human)?;
+                Ok(StoredAddr { canonical, human })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        proxies(deps.storage).save(&registered)?;
+    }
+
+    let end = state
+        .key_owners
+        .min(start.saturating_add(page_limit(limit)));
+    let mut migrated = 0u64;
+    for index in start..end {
+        let key_owner = key_owners_read(deps.storage).load(&index.to_be_bytes())?;
+        let (canonical, human) = migrate_address(deps.api, &key_owner.canonical, &key_owner.human)?;
+        let old_key = key_owner.canonical.as_slice();
+
+        // grantees may need migrating even when their owner doesn't
+        if let Some(grantees) = grants_read(deps.storage).may_load(old_key)? {
+            let grantees = grantees
+                .iter()
+                .map(|grantee| {
+                    let (canonical, human) =
+                        migrate_address(deps.api, &grantee.canonical, &grantee.human)?;
+                    Ok(StoredAddr { canonical, human })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            grants(deps.storage).remove(old_key);
+            grants(deps.storage).save(canonical.as_slice(), &grantees)?;
+        }
+        if let Some(mut switch) = dead_man_switches_read(deps.storage).may_load(old_key)? {
+            switch.beneficiaries = switch
+                .beneficiaries
+                .iter()
+                .map(|beneficiary| {
+                    let (canonical, human) =
+                        migrate_address(deps.api, &beneficiary.canonical, &beneficiary.human)?;
+                    Ok(StoredAddr { canonical, human })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            dead_man_switches(deps.storage).remove(old_key);
+            dead_man_switches(deps.storage).save(canonical.as_slice(), &switch)?;
+        }
+        if let Some(mut ceremony) = ceremonies_read(deps.storage).may_load(old_key)? {
+            for stored in ceremony
+                .participants
+                .iter_mut()
+                .chain(ceremony.checked_in.iter_mut())
+            {
+                let (canonical, human) =
+                    migrate_address(deps.api, &stored.canonical, &stored.human)?;
+                *stored = StoredAddr { canonical, human };
+            }
+            ceremonies(deps.storage).remove(old_key);
+            ceremonies(deps.storage).save(canonical.as_slice(), &ceremony)?;
+        }
+        if let Some(mut fragment_set) = fragment_sets_read(deps.storage).may_load(old_key)? {
+            for fragment in fragment_set.fragments.iter_mut() {
+                if let Some(proxy) = fragment.proxy.as_mut() {
+                    let (canonical, human) =
+                        migrate_address(deps.api, &proxy.canonical, &proxy.human)?;
+                    *proxy = StoredAddr { canonical, human };
+                }
+            }
+            fragment_sets(deps.storage).remove(old_key);
+            fragment_sets(deps.storage).save(canonical.as_slice(), &fragment_set)?;
+        }
+
+        if canonical == key_owner.canonical && human == key_owner.human {
+            continue;
+        }
+
+        if let Some(doc_hashes) = ciphertext_hashes_read(deps.storage).may_load(old_key)? {
+            for doc_hash in doc_hashes.iter() {
+                let mut stored = ciphertexts_read(deps.storage, &key_owner.canonical)
+                    .load(doc_hash.as_slice())?;
+                stored.owner = StoredAddr {
+                    canonical: canonical.clone(),
+                    human: human.clone(),
+                };
+                ciphertexts(deps.storage, &key_owner.canonical).remove(doc_hash.as_slice());
+                ciphertexts(deps.storage, &canonical).save(doc_hash.as_slice(), &stored)?;
+            }
+            ciphertext_hashes(deps.storage).remove(old_key);
+            ciphertext_hashes(deps.storage).save(canonical.as_slice(), &doc_hashes)?;
+        }
+
+        if canonical != key_owner.canonical {
+            if let Some(owner_labels) = labels_read(deps.storage).may_load(old_key)? {
+                for label in owner_labels.iter() {
+                    let record =
+                        keys_read(deps.storage, &key_owner.canonical).load(label.as_bytes())?;
+                    keys(deps.storage, &canonical).save(label.as_bytes(), &record)?;
+                    keys(deps.storage, &key_owner.canonical).remove(label.as_bytes());
+                }
+                labels(deps.storage).save(canonical.as_slice(), &owner_labels)?;
+                labels(deps.storage).remove(old_key);
+            }
+            move_viewing_key(deps.storage, &key_owner.canonical, &canonical)?;
+            if let Some(credits) = set_credits_read(deps.storage).may_load(old_key)? {
+                set_credits(deps.storage).save(canonical.as_slice(), &credits)?;
+                set_credits(deps.storage).remove(old_key);
+            }
+            if let Some(counted) = stats_counted_read(deps.storage).may_load(old_key)? {
+                stats_counted(deps.storage).save(canonical.as_slice(), &counted)?;
+                stats_counted(deps.storage).remove(old_key);
+            }
+            // key versions and the activity log stay where they are
+            let log_id = log_id(deps.storage, &key_owner.canonical)?;
+            log_ids(deps.storage).remove(old_key);
+            if log_id == canonical {
+                log_ids(deps.storage).remove(canonical.as_slice());
+            } else {
+                log_ids(deps.storage).save(canonical.as_slice(), &log_id)?;
+            }
+        }
+        key_owners(deps.storage).save(&index.to_be_bytes(), &StoredAddr { canonical, human })?;
+        migrated += 1;
+    }
+
+    deps.api
+        .debug(&format!("migrated {} stored addresses", migrated));
+    Ok(ResponseBuilder::new("migrate_addresses")
+        .log("migrated", migrated)
+        .log("next", end)
+        .build())
+}
+
+fn migrate_address(
+    api: &dyn Api,
+    canonical: &CanonicalAddr,
+    human: &Addr,
+) -> StdResult<(CanonicalAddr, Addr)> {
+    match api.addr_canonicalize(human.as_str()) {
+        Ok(new_canonical) => Ok((new_canonical, human.clone())),
+        Err(_) => Ok((canonical.clone(), api.addr_humanize(canonical)?)),
+    }
+}
+
+/// Appends the hash of a newly stored key to the transparency log, chaining it to the
+/// previous entry and recording a checkpoint every `CHECKPOINT_INTERVAL` entries.
+fn append_key_hash(
+    storage: &mut dyn Storage,
+    block_height: u64,
+    owner: &CanonicalAddr,
+    key: &[u8],
+) -> StdResult<()> {
+    let mut head = transparency_head(storage).load()?;
+    let owner_hash = Sha256::digest(owner.as_slice());
+    let key_hash = Sha256::digest(key);
+
+    let mut hasher = Sha256::new();
+    hasher.update(head.head.as_slice());
+    hasher.update(head.len.to_be_bytes());
+    hasher.update(block_height.to_be_bytes());
+    hasher.update(owner_hash);
+    hasher.update(key_hash);
+
+    let entry = TransparencyEntry {
+        index: head.len,
+        block_height,
+        owner_hash: Binary(owner_hash.to_vec()),
+        key_hash: Binary(key_hash.to_vec()),
+        chain_hash: Binary(hasher.finalize().to_vec()),
+    };
+    transparency_entries(storage).save(&entry.index.to_be_bytes(), &entry)?;
+    if (entry.index + 1) % CHECKPOINT_INTERVAL == 0 {
+        let checkpoint = entry.index / CHECKPOINT_INTERVAL;
+        transparency_checkpoints(storage).save(&checkpoint.to_be_bytes(), &entry)?;
+    }
+
+    head.len += 1;
+    head.head = entry.chain_hash;
+    transparency_head(storage).save(&head)
+}
+
+/// The address `owner`'s key versions and activity log are kept under, see `log_ids`.
+fn log_id(storage: &dyn Storage, owner: &CanonicalAddr) -> StdResult<CanonicalAddr> {
+    Ok(log_ids_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(|| owner.clone()))
+}
+
+/// Applies `change`, a change to `owner`'s keys or grants, to the running totals and marks
+/// them updated at `block_height`. While a recount is running, owners it hasn't reached yet
+/// are left for it to count.
+fn update_stats(
+    storage: &mut dyn Storage,
+    block_height: u64,
+    owner: &CanonicalAddr,
+    change: impl FnOnce(&mut Stats),
+) -> StdResult<()> {
+    let mut totals = stats_read(storage).may_load()?.unwrap_or_default();
+    if totals.recount_next.is_none()
+        || stats_counted_read(storage)
+            .may_load(owner.as_slice())?
+            .is_some()
+    {
+        change(&mut totals);
+    }
+    totals.last_updated = Some(block_height);
+    stats(storage).save(&totals)
+}
+
+/// Counts the keys and grants of the key owner index entries in `[next, next + limit)`
+/// into totals that predate the counters, see `migrate`.
+pub fn try_recount_stats(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let sender_address_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let state = config_read(deps.storage).load()?;
+    if sender_address_raw != state.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    let mut totals = stats_read(deps.storage).load()?;
+    let start = match totals.recount_next {
+        Some(next) => next,
+        None => return Err(StdError::generic_err("the totals are already counted").into()),
+    };
+
+    let end = state
+        .key_owners
+        .min(start.saturating_add(page_limit(limit)));
+    for index in start..end {
+        let owner = key_owners_read(deps.storage).load(&index.to_be_bytes())?;
+        let owner_labels = labels_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .unwrap_or_default();
+        if !owner_labels.is_empty() {
+            totals.keys += owner_labels.len() as u64;
+            totals.owners += 1;
+        }
+        totals.grants += grants_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .map_or(0, |grantees| grantees.len() as u64);
+        stats_counted(deps.storage).save(owner.canonical.as_slice(), &true)?;
+    }
+    totals.recount_next = if end < state.key_owners {
+        Some(end)
+    } else {
+        None
+    };
+    stats(deps.storage).save(&totals)?;
+
+    Ok(ResponseBuilder::new("recount_stats")
+        .log("counted", end - start)
+        .log("next", end)
+        .build())
+}
+
+/// Appends an entry to `owner`'s activity log, chaining it to their previous entry.
+fn record_activity(
+    storage: &mut dyn Storage,
+    block_height: u64,
+    owner: &CanonicalAddr,
+    action: &str,
+    label: Option<&str>,
+    counterparty: Option<&Addr>,
+    fee: Option<&Coin>,
+) -> StdResult<()> {
+    let owner = &log_id(storage, owner)?;
+    let len = activity_lens_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    let previous = match len {
+        0 => Binary(vec![0; 32]),
+        len => {
+            activity_read(storage, owner)
+                .load(&(len - 1).to_be_bytes())?
+                .chain_hash
+        }
+    };
+    let mut entry = ActivityEntry {
+        index: len,
+        block_height,
+        action: action.to_string(),
+        label: label.map(String::from),
+        counterparty: counterparty.cloned(),
+        fee: fee.cloned(),
+        chain_hash: Binary(vec![]),
+    };
+    entry.chain_hash = activity_hash(&previous, &entry);
+    activity(storage, owner).save(&len.to_be_bytes(), &entry)?;
+    activity_lens(storage).save(owner.as_slice(), &(len + 1))
+}
+
+/// The chain hash of `entry`: sha256 over `previous`, the big-endian index and height, then
+/// the action, label, counterparty and fee. Optional fields are a 0 byte when absent and a
+/// 1 byte otherwise, strings are prefixed with their big-endian u64 length, and a fee is its
+/// denom followed by its amount as a big-endian u128. The owner isn't part of the hash, so
+/// the log stays valid when their address is migrated.
+fn activity_hash(previous: &[u8], entry: &ActivityEntry) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(entry.index.to_be_bytes());
+    hasher.update(entry.block_height.to_be_bytes());
+    hash_str(&mut hasher, &entry.action);
+    hash_optional_str(&mut hasher, entry.label.as_deref());
+    hash_optional_str(&mut hasher, entry.counterparty.as_ref().map(Addr::as_str));
+    match &entry.fee {
+        Some(fee) => {
+            hasher.update([1]);
+            hash_str(&mut hasher, &fee.denom);
+            hasher.update(fee.amount.u128().to_be_bytes());
+        }
+        None => hasher.update([0]),
+    }
+    Binary(hasher.finalize().to_vec())
+}
+
+fn hash_str(hasher: &mut Sha256, value: &str) {
+    hasher.update((value.len() as u64).to_be_bytes());
+    hasher.update(value.as_bytes());
+}
+
+fn hash_optional_str(hasher: &mut Sha256, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            hasher.update([1]);
+            hash_str(hasher, value);
+        }
+        None => hasher.update([0]),
+    }
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    match msg {
+        QueryMsg::GetReencryptionKey { .. }
+        | QueryMsg::GetKeyVersion { .. }
+        | QueryMsg::GetFragment { .. }
+        | QueryMsg::GetCiphertext { .. }
+            if config_read(deps.storage).load()?.frozen =>
+        {
+            return Err(ContractError::Frozen {});
+        }
+        _ => {}
+    }
+    let response = match msg {
+        QueryMsg::GetReencryptionKey {
+            address,
+            key,
+            owner,
+            label,
+        } => {
+            let owner_raw = authorize_reader(deps, &env.block, &address, &key, owner)?;
+            to_binary(&query_reencryption_key(
+                deps,
+                &env.block,
+                &owner_raw,
+                &label_or_default(label),
+            )?)
+        }
+        QueryMsg::GetKeyVersion {
+            address,
+            key,
+            owner,
+            label,
+            version,
+        } => {
+            let owner_raw = authorize_reader(deps, &env.block, &address, &key, owner)?;
+            to_binary(&query_key_version(
+                deps,
+                &env.block,
+                &owner_raw,
+                &label_or_default(label),
+                version,
+            )?)
+        }
+        QueryMsg::GetLatestVersion {
+            address,
+            key,
+            owner,
+            label,
+        } => {
+            let owner_raw = authorize_reader(deps, &env.block, &address, &key, owner)?;
+            to_binary(&query_latest_version(
+                deps,
+                &owner_raw,
+                &label_or_default(label),
+            )?)
+        }
+        QueryMsg::ListLabels {
+            address,
+            key,
+            owner,
+        } => {
+            let owner_raw = authorize_reader(deps, &env.block, &address, &key, owner)?;
+            to_binary(&query_labels(deps, &owner_raw)?)
+        }
+        QueryMsg::GetGrantees { address, key } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            to_binary(&query_grantees(deps, &address_raw)?)
+        }
+        QueryMsg::GetViewingKeyInfo { address, key } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            to_binary(&query_viewing_key_info(deps, &address_raw)?)
+        }
+        QueryMsg::GetSetCredits { address, key } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            to_binary(&SetCreditsResponse {
+                credits: set_credits_read(deps.storage)
+                    .may_load(address_raw.as_slice())?
+                    .unwrap_or_default(),
+            })
+        }
+        QueryMsg::GetActivityStatement {
+            address,
+            key,
+            start_height,
+            end_height,
+            start,
+            limit,
+        } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            to_binary(&query_activity_statement(
+                deps,
+                &address_raw,
+                start_height,
+                end_height,
+                start,
+                limit,
+            )?)
+        }
+        QueryMsg::ListAllKeys {
+            address,
+            key,
+            start_after,
+            limit,
+        } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            if address_raw != config_read(deps.storage).load()?.owner {
+                return Err(ContractError::Unauthorized {});
+            }
+            to_binary(&query_all_keys(deps, start_after, limit)?)
+        }
+        QueryMsg::GetReleaseStatus {
+            address,
+            key,
+            owner,
+        } => {
+            let viewer = authenticate(deps, &address, &key)?;
+            let owner_raw = match owner {
+                Some(owner) => deps.api.addr_canonicalize(&owner)?,
+                None => viewer.clone(),
+            };
+            to_binary(&query_release_status(
+                deps, &env.block, &viewer, &owner_raw,
+            )?)
+        }
+        QueryMsg::GetCeremonyStatus {
+            address,
+            key,
+            owner,
+        } => {
+            let viewer = authenticate(deps, &address, &key)?;
+            let owner_raw = match owner {
+                Some(owner) => deps.api.addr_canonicalize(&owner)?,
+                None => viewer.clone(),
+            };
+            to_binary(&query_ceremony_status(
+                deps, &env.block, &viewer, &owner_raw,
+            )?)
+        }
+        QueryMsg::GetFragment {
+            address,
+            key,
+            owner,
+        } => {
+            let proxy = authenticate(deps, &address, &key)?;
+            let owner_raw = deps.api.addr_canonicalize(&owner)?;
+            to_binary(&query_fragment(deps, &owner_raw, &proxy)?)
+        }
+        QueryMsg::GetFragmentAssignments { address, key } => {
+            let address_raw = authenticate(deps, &address, &key)?;
+            to_binary(&query_fragment_assignments(deps, &address_raw)?)
+        }
+        QueryMsg::GetCiphertext {
+            address,
+            key,
+            owner,
+            doc_hash,
+        } => {
+            let owner_raw = authorize_reader(deps, &env.block, &address, &key, owner)?;
+            let stored = ciphertexts_read(deps.storage, &owner_raw).load(doc_hash.as_slice())?;
+            to_binary(&CiphertextResponse {
+                owner: stored.owner.human,
+                ciphertext: stored.ciphertext,
+                block_height: stored.block_height,
+            })
+        }
+        QueryMsg::ListProxies { start, limit } => to_binary(&query_proxies(deps, start, limit)?),
+        QueryMsg::GetContractVersion {} => to_binary(&contract_version_read(deps.storage).load()?),
+        QueryMsg::GetStats {} => to_binary(&stats_read(deps.storage).load()?),
+        QueryMsg::GetOwner {} => to_binary(&query_owner(deps)?),
+        QueryMsg::GetSetters {} => to_binary(&query_setters(deps)?),
+        QueryMsg::GetConfig {} => to_binary(&query_config(deps)?),
+        QueryMsg::GetCollectedFees {} => to_binary(&CollectedFeesResponse {
+            fees: collected_fees_read(deps.storage)
+                .may_load()?
+                .unwrap_or_default(),
+            tokens: collected_tokens_read(deps.storage)
+                .may_load()?
+                .unwrap_or_default(),
+        }),
+        QueryMsg::GetRetirementStatus {} => to_binary(&query_retirement_status(deps)?),
+        QueryMsg::GetMaintenanceStatus {} => to_binary(&query_maintenance_status(deps)?),
+        QueryMsg::Health {} => to_binary(&query_health(deps)?),
+        QueryMsg::GetTransparencyHead {} => to_binary(&query_transparency_head(deps)?),
+        QueryMsg::GetTransparencyEntries { start, limit } => {
+            to_binary(&query_transparency_entries(deps, start, limit)?)
+        }
+        QueryMsg::GetTransparencyCheckpoints { start, limit } => {
+            to_binary(&query_transparency_checkpoints(deps, start, limit)?)
+        }
+    };
+    Ok(response?)
+}
+
+/// Checks `key` against the viewing key stored for `address`, returning its canonical form.
+fn authenticate(deps: Deps, address: &str, key: &str) -> Result<CanonicalAddr, ContractError> {
+    let address_raw = deps.api.addr_canonicalize(address)?;
+    let viewing_key = ViewingKey(key.to_string());
+
+    match read_viewing_key(deps.storage, &address_raw)? {
+        Some(stored) if viewing_key.check_viewing_key(&stored.salt, &stored.hash) => {
+            Ok(address_raw)
+        }
+        Some(_) => Err(ContractError::Unauthorized {}),
+        None => {
+            // compare against a dummy key so the response time doesn't reveal that
+            // no viewing key was ever set for this address
+            viewing_key.check_viewing_key(&[0u8; SALT_SIZE], &[0u8; VIEWING_KEY_SIZE]);
+            Err(ContractError::Unauthorized {})
+        }
+    }
+}
+
+/// Authenticates `address` and checks it may read the key of `owner`, which defaults to
+/// `address` itself. Returns the canonical address of the owner.
+fn authorize_reader(
+    deps: Deps,
+    block: &BlockInfo,
+    address: &str,
+    key: &str,
+    owner: Option<String>,
+) -> Result<CanonicalAddr, ContractError> {
+    let viewer = authenticate(deps, address, key)?;
+    let owner_raw = match owner {
+        Some(owner) => deps.api.addr_canonicalize(&owner)?,
+        None => viewer.clone(),
+    };
+    if owner_raw != viewer && !is_grantee(deps, &owner_raw, &viewer)? {
+        check_released(deps, block, &owner_raw, &viewer)?;
+    }
+    Ok(owner_raw)
+}
+
+/// Checks `owner`'s key has been released to `reader`, either by a completed ceremony
+/// `reader` took part in or by a dead-man switch that lists them and has fired.
+fn check_released(
+    deps: Deps,
+    block: &BlockInfo,
+    owner: &CanonicalAddr,
+    reader: &CanonicalAddr,
+) -> Result<(), ContractError> {
+    let participant = match ceremonies_read(deps.storage).may_load(owner.as_slice())? {
+        Some(ceremony) if ceremony.is_participant(reader) => {
+            if ceremony.completed_at.is_some() {
+                return Ok(());
+            }
+            true
+        }
+        _ => false,
+    };
+    match release_deadline(deps, owner, reader)? {
+        Some(deadline) if block.time.seconds() >= deadline => Ok(()),
+        Some(deadline) => Err(ContractError::NotReleased { deadline }),
+        None if participant => Err(ContractError::CeremonyIncomplete {}),
+        None => Err(ContractError::Unauthorized {}),
+    }
+}
+
+/// The deadline of `owner`'s dead-man switch, if it lists `beneficiary`.
+fn release_deadline(
+    deps: Deps,
+    owner: &CanonicalAddr,
+    beneficiary: &CanonicalAddr,
+) -> StdResult<Option<u64>> {
+    let switch = match dead_man_switches_read(deps.storage).may_load(owner.as_slice())? {
+        Some(switch) => switch,
+        None => return Ok(None),
+    };
+    let listed = switch
+        .beneficiaries
+        .iter()
+        .any(|stored| &stored.canonical == beneficiary);
+    Ok(if listed {
+        Some(switch.deadline())
+    } else {
+        None
+    })
+}
+
+fn is_grantee(deps: Deps, owner: &CanonicalAddr, grantee: &CanonicalAddr) -> StdResult<bool> {
+    let grantees = grants_read(deps.storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    Ok(grantees.iter().any(|stored| &stored.canonical == grantee))
+}
+
+fn query_grantees(deps: Deps, owner: &CanonicalAddr) -> StdResult<GranteesResponse> {
+    let grantees = grants_read(deps.storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    Ok(GranteesResponse {
+        grantees: grantees.into_iter().map(|stored| stored.human).collect(),
+    })
+}
+
+fn query_viewing_key_info(deps: Deps, owner: &CanonicalAddr) -> StdResult<ViewingKeyInfoResponse> {
+    let stored =
+        read_viewing_key(deps.storage, owner)?.ok_or_else(|| StdError::not_found("viewing key"))?;
+    Ok(ViewingKeyInfoResponse {
+        created_at: stored.created_at,
+    })
+}
+
+fn query_activity_statement(
+    deps: Deps,
+    owner: &CanonicalAddr,
+    start_height: u64,
+    end_height: u64,
+    start: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ActivityStatementResponse> {
+    if start_height > end_height {
+        return Err(StdError::generic_err(
+            "start height must not be after end height",
+        ));
+    }
+    let log_id = log_id(deps.storage, owner)?;
+    let len = activity_lens_read(deps.storage)
+        .may_load(log_id.as_slice())?
+        .unwrap_or_default();
+    let log = activity_read(deps.storage, &log_id);
+
+    // heights only grow along the log, so the first entry in range can be searched for
+    let (mut first, mut high) = (0, len);
+    while first < high {
+        let mid = first + (high - first) / 2;
+        if log.load(&mid.to_be_bytes())?.block_height < start_height {
+            first = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let first = first.max(start.unwrap_or(0)).min(len);
+    let previous_hash = match first {
+        0 => Binary(vec![0; 32]),
+        first => log.load(&(first - 1).to_be_bytes())?.chain_hash,
+    };
+
+    let end = len.min(first.saturating_add(page_limit(limit)));
+    let mut entries = Vec::new();
+    for index in first..end {
+        let entry = log.load(&index.to_be_bytes())?;
+        if entry.block_height > end_height {
+            break;
+        }
+        entries.push(entry);
+    }
+    let next = if entries.len() as u64 == end - first
+        && end < len
+        && log.load(&end.to_be_bytes())?.block_height <= end_height
+    {
+        Some(end)
+    } else {
+        None
+    };
+    let digest = entries
+        .last()
+        .map_or_else(|| previous_hash.clone(), |entry| entry.chain_hash.clone());
+
+    Ok(ActivityStatementResponse {
+        owner: deps.api.addr_humanize(owner)?,
+        start_height,
+        end_height,
+        entries,
+        previous_hash,
+        digest,
+        next,
+    })
+}
+
+fn query_reencryption_key(
+    deps: Deps,
+    block: &BlockInfo,
+    address_raw: &CanonicalAddr,
+    label: &str,
+) -> StdResult<ReencryptionKeyResponse> {
+    let record = keys_read(deps.storage, address_raw).load(label.as_bytes())?;
+    Ok(key_response(block, record))
+}
+
+fn query_key_version(
+    deps: Deps,
+    block: &BlockInfo,
+    owner: &CanonicalAddr,
+    label: &str,
+    version: u64,
+) -> StdResult<ReencryptionKeyResponse> {
+    let current = keys_read(deps.storage, owner).load(label.as_bytes())?;
+    let record = if version == current.version {
+        current
+    } else if version >= 1
+        && version < current.version
+        && current.version - version <= MAX_KEY_VERSIONS
+    {
+        key_versions_read(deps.storage, &log_id(deps.storage, owner)?, label)
+            .load(&version.to_be_bytes())?
+    } else {
+        return Err(StdError::not_found("key version"));
+    };
+    Ok(key_response(block, record))
+}
+
+/// Withholds the key of a record that has expired as of `block`.
+fn key_response(block: &BlockInfo, record: KeyRecord) -> ReencryptionKeyResponse {
+    let expired = record
+        .expires_at
+        .map_or(false, |expires_at| expires_at.is_expired(block));
+    ReencryptionKeyResponse {
+        status: if expired {
+            KeyStatus::Expired
+        } else {
+            KeyStatus::Active
+        },
+        reencryption_key: if expired {
+            None
+        } else {
+            Some(record.reencryption_key)
+        },
+        version: record.version,
+        expires_at: record.expires_at,
+        description: record.description,
+        document_cid: record.document_cid,
+        capsule: record.capsule,
+    }
+}
+
+fn query_latest_version(
+    deps: Deps,
+    owner: &CanonicalAddr,
+    label: &str,
+) -> StdResult<LatestVersionResponse> {
+    let current = keys_read(deps.storage, owner).load(label.as_bytes())?;
+    Ok(LatestVersionResponse {
+        version: current.version,
+    })
+}
+
+fn query_labels(deps: Deps, owner: &CanonicalAddr) -> StdResult<LabelsResponse> {
+    let labels = labels_read(deps.storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    Ok(LabelsResponse { labels })
+}
+
+fn query_ceremony_status(
+    deps: Deps,
+    block: &BlockInfo,
+    viewer: &CanonicalAddr,
+    owner: &CanonicalAddr,
+) -> Result<CeremonyStatusResponse, ContractError> {
+    let ceremony = ceremonies_read(deps.storage).load(owner.as_slice())?;
+    if viewer != owner && !ceremony.is_participant(viewer) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let lapsed = ceremony.has_lapsed(block.time.seconds());
+    Ok(CeremonyStatusResponse {
+        participants: ceremony
+            .participants
+            .into_iter()
+            .map(|stored| stored.human)
+            .collect(),
+        window: ceremony.window,
+        round_started_at: if lapsed {
+            None
+        } else {
+            ceremony.round_started_at
+        },
+        checked_in: if lapsed {
+            vec![]
+        } else {
+            ceremony
+                .checked_in
+                .into_iter()
+                .map(|stored| stored.human)
+                .collect()
+        },
+        completed_at: ceremony.completed_at,
+    })
+}
+
+fn query_release_status(
+    deps: Deps,
+    block: &BlockInfo,
+    viewer: &CanonicalAddr,
+    owner: &CanonicalAddr,
+) -> Result<ReleaseStatusResponse, ContractError> {
+    let switch = dead_man_switches_read(deps.storage).load(owner.as_slice())?;
+    if viewer != owner
+        && !switch
+            .beneficiaries
+            .iter()
+            .any(|stored| &stored.canonical == viewer)
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+    let now = block.time.seconds();
+    let deadline = switch.deadline();
+    Ok(ReleaseStatusResponse {
+        released: now >= deadline,
+        deadline,
+        remaining: deadline.saturating_sub(now),
+    })
+}
+
+/// Returns only the fragment of `owner`'s set that is assigned to `proxy`.
+fn query_fragment(
+    deps: Deps,
+    owner: &CanonicalAddr,
+    proxy: &CanonicalAddr,
+) -> Result<FragmentResponse, ContractError> {
+    let fragment_set = fragment_sets_read(deps.storage).load(owner.as_slice())?;
+    let (index, fragment) = fragment_set
+        .fragments
+        .into_iter()
+        .enumerate()
+        .find(|(_, fragment)| {
+            fragment
+                .proxy
+                .as_ref()
+                .map_or(false, |holder| &holder.canonical == proxy)
+        })
+        .ok_or_else(|| StdError::not_found("fragment"))?;
+    if !is_registered_proxy(deps.storage, proxy)? {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(FragmentResponse {
+        index: index as u8,
+        threshold: fragment_set.threshold,
+        fragment: fragment.fragment,
+    })
+}
+
+fn query_fragment_assignments(
+    deps: Deps,
+    owner: &CanonicalAddr,
+) -> StdResult<FragmentAssignmentsResponse> {
+    let fragment_set = fragment_sets_read(deps.storage).load(owner.as_slice())?;
+    Ok(FragmentAssignmentsResponse {
+        threshold: fragment_set.threshold,
+        assignments: fragment_set
+            .fragments
+            .into_iter()
+            .map(|fragment| fragment.proxy.map(|proxy| proxy.human))
+            .collect(),
+    })
+}
+
+fn query_proxies(deps: Deps, start: u64, limit: Option<u32>) -> StdResult<ProxiesResponse> {
+    let registered = proxies_read(deps.storage).may_load()?.unwrap_or_default();
+    Ok(ProxiesResponse {
+        proxies: registered
+            .into_iter()
+            .skip(start as usize)
+            .take(page_limit(limit) as usize)
+            .map(|proxy| proxy.human)
+            .collect(),
+    })
+}
+
+fn query_all_keys(
+    deps: Deps,
+    start_after: Option<KeyCursor>,
+    limit: Option<u32>,
+) -> StdResult<AllKeysResponse> {
+    // a page of nothing would hand back the cursor it was given
+    let limit = page_limit(limit).max(1) as usize;
+    let key_owner_count = config_read(deps.storage).load()?.key_owners;
+    let (first_owner, mut after_label) = match start_after {
+        Some(cursor) => (cursor.owner_index, Some(cursor.label)),
+        None => (0, None),
+    };
+    let end = key_owner_count.min(first_owner.saturating_add(MAX_OWNERS_SCANNED));
+
+    let mut listed: Vec<(u64, KeyMetadata)> = Vec::with_capacity(limit);
+    let mut next = None;
+    'owners: for index in first_owner..end {
+        let owner = key_owners_read(deps.storage).load(&index.to_be_bytes())?;
+        let mut owner_labels = labels_read(deps.storage)
+            .may_load(owner.canonical.as_slice())?
+            .unwrap_or_default();
+        owner_labels.sort();
+        // only the cursor's own owner is resumed part way through
+        let after_label = after_label.take();
+        for label in owner_labels {
+            if after_label.as_ref().map_or(false, |after| &label <= after) {
+                continue;
+            }
+            if listed.len() == limit {
+                next = listed.last().map(|(index, metadata)| KeyCursor {
+                    owner_index: *index,
+                    label: metadata.label.clone(),
+                });
+                break 'owners;
+            }
+            let record = keys_read(deps.storage, &owner.canonical).load(label.as_bytes())?;
+            listed.push((
+                index,
+                KeyMetadata {
+                    owner: owner.human.clone(),
+                    label,
+                    version: record.version,
+                    expires_at: record.expires_at,
+                    description: record.description,
+                    document_cid: record.document_cid,
+                },
+            ));
+        }
+    }
+    if next.is_none() && end < key_owner_count {
+        next = Some(KeyCursor {
+            owner_index: end,
+            label: String::new(),
+        });
+    }
+
+    Ok(AllKeysResponse {
+        keys: listed.into_iter().map(|(_, metadata)| metadata).collect(),
+        next,
+    })
+}
+
+fn query_owner(deps: Deps) -> StdResult<OwnerResponse> {
+    let state = config_read(deps.storage).load()?;
+    Ok(OwnerResponse {
+        owner: state.owner_human,
+        pending_owner: state.pending_owner.map(|pending_owner| pending_owner.human),
+        guardian: state.guardian.map(|guardian| guardian.human),
+    })
+}
+
+fn query_setters(deps: Deps) -> StdResult<SettersResponse> {
+    let state = config_read(deps.storage).load()?;
+    Ok(SettersResponse {
+        mode: state.set_mode,
+        setters: state
+            .setters
+            .into_iter()
+            .map(|setter| setter.human)
+            .collect(),
+    })
+}
+
+fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let parameters = contract_config_read(deps.storage).load()?;
+    Ok(ConfigResponse {
+        max_keys_per_owner: parameters.max_keys_per_owner,
+        set_fee: parameters.set_fee,
+        key_type: parameters.key_type,
+        max_key_length: parameters.max_key_length,
+        default_inactivity_window: parameters.default_inactivity_window,
+        token_set_fee: parameters.token_set_fee,
+        set_mode: config_read(deps.storage).load()?.set_mode,
+    })
+}
+
+fn query_retirement_status(deps: Deps) -> StdResult<RetirementStatusResponse> {
+    let state = config_read(deps.storage).load()?;
+    Ok(match state.retirement {
+        None => RetirementStatusResponse {
+            stage: RetirementStage::Active,
+            announced_at: None,
+            purge_after: None,
+        },
+        Some(retirement) => RetirementStatusResponse {
+            stage: if retirement.purged {
+                RetirementStage::Purged
+            } else {
+                RetirementStage::Retiring
+            },
+            announced_at: Some(retirement.announced_at),
+            purge_after: Some(retirement.purge_after),
+        },
+    })
+}
+
+fn query_maintenance_status(deps: Deps) -> StdResult<MaintenanceStatusResponse> {
+    let maintenance = config_read(deps.storage).load()?.maintenance;
+    Ok(MaintenanceStatusResponse {
+        maintenance: maintenance.is_some(),
+        eta: maintenance.and_then(|maintenance| maintenance.eta),
+    })
+}
+
+fn query_health(deps: Deps) -> StdResult<HealthResponse> {
+    let state = config_read(deps.storage).load()?;
+    let progress = purge_progress_read(deps.storage).may_load()?;
+    Ok(HealthResponse {
+        paused: state.paused,
+        frozen: state.frozen,
+        maintenance: state.maintenance.is_some(),
+        contract_version: contract_version_read(deps.storage).load()?.version,
+        last_purge_height: progress.as_ref().map(|progress| progress.last_height),
+        purge_backlog: state
+            .key_owners
+            .saturating_sub(progress.map_or(0, |progress| progress.next)),
+        retirement_backlog: state
+            .retirement
+            .as_ref()
+            .map(|retirement| state.key_owners.saturating_sub(retirement.purge_cursor)),
+    })
+}
+
+fn query_transparency_head(deps: Deps) -> StdResult<TransparencyHeadResponse> {
+    let head = transparency_head_read(deps.storage).load()?;
+    Ok(TransparencyHeadResponse {
+        len: head.len,
+        head: head.head,
+    })
+}
+
+fn query_transparency_entries(
+    deps: Deps,
+    start: u64,
+    limit: Option<u32>,
+) -> StdResult<TransparencyEntriesResponse> {
+    let len = transparency_head_read(deps.storage).load()?.len;
+    let end = len.min(start.saturating_add(page_limit(limit)));
+    let entries = transparency_entries_read(deps.storage);
+    let entries = (start..end)
+        .map(|index| entries.load(&index.to_be_bytes()))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(TransparencyEntriesResponse { entries })
+}
+
+fn query_transparency_checkpoints(
+    deps: Deps,
+    start: u64,
+    limit: Option<u32>,
+) -> StdResult<TransparencyEntriesResponse> {
+    let len = transparency_head_read(deps.storage).load()?.len / CHECKPOINT_INTERVAL;
+    let end = len.min(start.saturating_add(page_limit(limit)));
+    let checkpoints = transparency_checkpoints_read(deps.storage);
+    let entries = (start..end)
+        .map(|checkpoint| checkpoints.load(&checkpoint.to_be_bytes()))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(TransparencyEntriesResponse { entries })
+}
+
+fn page_limit(limit: Option<u32>) -> u64 {
+    limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{attr, coin, coins, from_binary, SubMsg, Timestamp};
+
+    use crate::response::BLOCK_SIZE;
+
+    /// Stores the viewing key `<address>_key` for `address` directly, since the keys
+    /// CreateViewingKey hands out can't be known in advance.
+    fn set_viewing_key(deps: DepsMut, address: &str) {
+        let address_raw = deps.api.addr_canonicalize(address).unwrap();
+        let key = ViewingKey(format!("{}_key", address));
+        write_viewing_key(deps.storage, &address_raw, &key, [7; SALT_SIZE], 0).unwrap();
+    }
+
+    fn query_key(deps: Deps, address: &str) -> Result<ReencryptionKeyResponse, ContractError> {
+        let msg = QueryMsg::GetReencryptionKey {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: None,
+            label: None,
+        };
+        Ok(from_binary(&query(deps, mock_env(), msg)?)?)
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(1000, "earth"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        set_viewing_key(deps.as_mut(), "creator");
+
+        // it worked, nobody has stored a key yet
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn configured_initialization() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            admin: Some("admin".to_string()),
+            max_keys_per_owner: Some(2),
+            set_fee: Some(coin(100, "uscrt")),
+            key_type: Some(KeyType::X25519),
+            default_inactivity_window: Some(600),
+        };
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOwner {}).unwrap();
+        let value: OwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(Addr::unchecked("admin"), value.owner);
+
+        let set = |label: &str| ExecuteMsg::Set {
+            label: Some(label.to_string()),
+            reencryption_key: Binary(vec![9; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("anyone", &coins(99, "uscrt"));
+        match execute(deps.as_mut(), mock_env(), info, set("will")) {
+            Err(ContractError::InsufficientFee { required }) => {
+                assert_eq!(coin(100, "uscrt"), required)
+            }
+            _ => panic!("Must return insufficient fee error"),
+        }
+        let info = mock_info("anyone", &coins(100, "uscrt"));
+        for label in ["will", "deeds"].iter() {
+            let _res = execute(deps.as_mut(), mock_env(), info.clone(), set(label)).unwrap();
+        }
+        match execute(deps.as_mut(), mock_env(), info.clone(), set("letters")) {
+            Err(ContractError::QuotaExceeded { max, .. }) => assert_eq!(2, max),
+            _ => panic!("Must return quota exceeded error"),
+        }
+        // replacing a stored key doesn't count against the quota
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), set("will")).unwrap();
+
+        // the key type applies from instantiation on
+        let msg = ExecuteMsg::Set {
+            label: Some("will".to_string()),
+            reencryption_key: Binary(vec![9; 64]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
+            Err(ContractError::InvalidKey { .. }) => {}
+            _ => panic!("Must return invalid key error"),
+        }
+
+        let msg = ExecuteMsg::SetDeadManSwitch {
+            inactivity_window: None,
+            beneficiaries: vec!["heir".to_string()],
+        };
+        let env = mock_env();
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(
+            attr("deadline", (env.block.time.seconds() + 600).to_string()),
+            res.attributes[1]
+        );
+    }
+
+    #[test]
+    fn zero_parameters_rejected() {
+        let mut deps = mock_dependencies();
+
+        let zeroed = vec![
+            InstantiateMsg {
+                max_keys_per_owner: Some(0),
+                ..InstantiateMsg::default()
+            },
+            InstantiateMsg {
+                set_fee: Some(coin(0, "uscrt")),
+                ..InstantiateMsg::default()
+            },
+            InstantiateMsg {
+                default_inactivity_window: Some(0),
+                ..InstantiateMsg::default()
+            },
+        ];
+        for msg in zeroed {
+            let info = mock_info("creator", &[]);
+            match instantiate(deps.as_mut(), mock_env(), info, msg) {
+                Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+                _ => panic!("Must return generic error"),
+            }
+        }
+    }
+
+    #[test]
+    fn set() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        set_viewing_key(deps.as_mut(), "anyone");
+
+        // every sender sets their own key
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![1; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![66; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // neither overwrote the other
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![1; 32])), value.reencryption_key);
+
+        let value = query_key(deps.as_ref(), "anyone").unwrap();
+        assert_eq!(Some(Binary(vec![66; 32])), value.reencryption_key);
+
+        // setting again replaces only the sender's key
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![2; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // indexers can follow key updates from the attributes alone
+        assert_eq!(attr("action", "set"), res.attributes[0]);
+        assert_eq!(attr("owner", "creator"), res.attributes[1]);
+        assert_eq!(attr("label", DEFAULT_LABEL), res.attributes[2]);
+        assert_eq!(attr("version", "2"), res.attributes[3]);
+        assert_eq!(
+            attr("height", mock_env().block.height.to_string()),
+            res.attributes[4]
+        );
+
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(2, state.key_owners);
+    }
+
+    #[test]
+    fn reset() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg::default();
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        set_viewing_key(deps.as_mut(), "anyone");
+
+        // there is nothing to reset before a key was set
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Reset { label: None };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let set_msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![55; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _set_res = execute(deps.as_mut(), mock_env(), info, set_msg).unwrap();
+
+        let info = mock_info("anyone", &coins(2, "token"));
+        let set_msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![56; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _set_res = execute(deps.as_mut(), mock_env(), info, set_msg).unwrap();
+
+        // reset the creator's key now
+        let info = mock_info("creator", &coins(2, "token"));
+        let reset_msg = ExecuteMsg::Reset { label: None };
+        let reset_res = execute(deps.as_mut(), mock_env(), info, reset_msg).unwrap();
+        assert_eq!(attr("action", "reset"), reset_res.attributes[0]);
+        assert_eq!(attr("owner", "creator"), reset_res.attributes[1]);
+        assert_eq!(attr("version", "2"), reset_res.attributes[3]);
+
+        // should now be 0
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![0; 32])), value.reencryption_key);
+
+        // and the other key is untouched
+        let value = query_key(deps.as_ref(), "anyone").unwrap();
+        assert_eq!(Some(Binary(vec![56; 32])), value.reencryption_key);
+    }
+
+    #[test]
+    fn retire() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![7; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // stored by an address without any keys
+        let msg = ExecuteMsg::StoreCiphertext {
+            doc_hash: Binary(vec![12; 32]),
+            ciphertext: Binary(vec![1; 64]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("writer", &[]), msg).unwrap();
+
+        // the notice period can't be shorter than the minimum
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Retire {
+            notice_period: MIN_RETIREMENT_NOTICE - 1,
+        };
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_err());
+
+        // only the owner can retire the contract
+        let unauth_info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Retire {
+            notice_period: MIN_RETIREMENT_NOTICE,
+        };
+        match execute(deps.as_mut(), mock_env(), unauth_info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let announced_at = mock_env().block.time.seconds();
+        let msg = ExecuteMsg::Retire {
+            notice_period: MIN_RETIREMENT_NOTICE,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetRetirementStatus {}).unwrap();
+        let value: RetirementStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(RetirementStage::Retiring, value.stage);
+        assert_eq!(
+            Some(announced_at + MIN_RETIREMENT_NOTICE),
+            value.purge_after
+        );
+
+        // new keys are blocked while retiring
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![8; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        assert_eq!(
+            Err(ContractError::Retiring {}),
+            execute(deps.as_mut(), mock_env(), info, msg)
+        );
+
+        // purging before the deadline fails
+        let info = mock_info("creator", &coins(2, "token"));
+        assert!(execute(deps.as_mut(), mock_env(), info, ExecuteMsg::PurgeRetired {}).is_err());
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_seconds(announced_at + MIN_RETIREMENT_NOTICE);
+        let _res = execute(deps.as_mut(), env, info, ExecuteMsg::PurgeRetired {}).unwrap();
+
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let writer = deps.api.addr_canonicalize("writer").unwrap();
+        assert_eq!(
+            None,
+            ciphertexts_read(&deps.storage, &writer)
+                .may_load(&[12; 32])
+                .unwrap()
+        );
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetRetirementStatus {}).unwrap();
+        let value: RetirementStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(RetirementStage::Purged, value.stage);
+
+        // a purged contract can't be brought back
+        let info = mock_info("creator", &coins(2, "token"));
+        assert!(execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::CancelRetirement {}
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn transparency_log() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        for i in 0..CHECKPOINT_INTERVAL {
+            let info = mock_info("creator", &coins(2, "token"));
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![i as u8 + 1; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Reset { label: None },
+        )
+        .unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetTransparencyHead {}).unwrap();
+        let head: TransparencyHeadResponse = from_binary(&res).unwrap();
+        assert_eq!(CHECKPOINT_INTERVAL + 1, head.len);
+
+        let msg = QueryMsg::GetTransparencyEntries {
+            start: 0,
+            limit: Some(2),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: TransparencyEntriesResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.entries.len());
+        assert_eq!(
+            Sha256::digest(&[1u8; 32]).as_slice(),
+            value.entries[0].key_hash.as_slice()
+        );
+
+        // every entry is chained to the one before it
+        let mut hasher = Sha256::new();
+        hasher.update(value.entries[0].chain_hash.as_slice());
+        hasher.update(1u64.to_be_bytes());
+        hasher.update(value.entries[1].block_height.to_be_bytes());
+        hasher.update(value.entries[1].owner_hash.as_slice());
+        hasher.update(value.entries[1].key_hash.as_slice());
+        assert_eq!(
+            hasher.finalize().as_slice(),
+            value.entries[1].chain_hash.as_slice()
+        );
+
+        // the reset is logged as the last entry and is the current head
+        let msg = QueryMsg::GetTransparencyEntries {
+            start: CHECKPOINT_INTERVAL,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: TransparencyEntriesResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.entries.len());
+        assert_eq!(head.head, value.entries[0].chain_hash);
+
+        let msg = QueryMsg::GetTransparencyCheckpoints {
+            start: 0,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: TransparencyEntriesResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.entries.len());
+        assert_eq!(CHECKPOINT_INTERVAL - 1, value.entries[0].index);
+    }
+
+    #[test]
+    fn pause() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let set = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![2; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, set.clone()).unwrap();
+
+        // only the owner can pause
+        let info = mock_info("anyone", &[]);
+        match execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Pause {}) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Pause {}).unwrap();
+
+        let blocked = vec![
+            set.clone(),
+            ExecuteMsg::Reset { label: None },
+            ExecuteMsg::Grant {
+                grantee: "heir".to_string(),
+            },
+            ExecuteMsg::MintRetrievalToken {
+                owner: None,
+                label: None,
+                expires_at: Expiration::AtHeight(u64::MAX),
+            },
+            ExecuteMsg::SetDeadManSwitch {
+                inactivity_window: Some(100),
+                beneficiaries: vec!["heir".to_string()],
+            },
+            ExecuteMsg::SetCeremony {
+                participants: vec!["heir".to_string()],
+                window: 100,
+            },
+            ExecuteMsg::SetFragments {
+                fragments: vec![Binary(vec![1; 32]), Binary(vec![2; 32])],
+                threshold: 2,
+                proxies: vec!["proxy".to_string(), "proxy".to_string()],
+            },
+            ExecuteMsg::AssignFragment {
+                index: 0,
+                proxy: "proxy".to_string(),
+            },
+            ExecuteMsg::StoreCapsule {
+                label: None,
+                capsule: Binary(vec![11; 98]),
+            },
+            ExecuteMsg::StoreCiphertext {
+                doc_hash: Binary(vec![12; 32]),
+                ciphertext: Binary(vec![1; 64]),
+            },
+            ExecuteMsg::DeleteCiphertext {
+                doc_hash: Binary(vec![12; 32]),
+            },
+        ];
+        for msg in blocked {
+            let info = mock_info("creator", &[]);
+            match execute(deps.as_mut(), mock_env(), info, msg) {
+                Err(ContractError::ContractPaused {}) => {}
+                _ => panic!("Must return contract paused error"),
+            }
+        }
+
+        // everything else, like registering a proxy and reading keys, keeps working
+        let info = mock_info("proxy", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::RegisterProxy {},
+        )
+        .unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Unpause {}).unwrap();
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, set).unwrap();
+    }
+
+    #[test]
+    fn maintenance_mode() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![2; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // only the owner can enter maintenance mode
+        let unauth_info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::SetMaintenanceMode {
+            eta: Some(1_600_000_000),
+        };
+        match execute(deps.as_mut(), mock_env(), unauth_info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::SetMaintenanceMode {
+            eta: Some(1_600_000_000),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // writes are rejected, even from the owner
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![3; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::MaintenanceMode { eta }) => {
+                assert_eq!(Some(1600000000), eta)
+            }
+            _ => panic!("Must return maintenance mode error"),
+        }
+
+        // queries keep working
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetMaintenanceStatus {}).unwrap();
+        let value: MaintenanceStatusResponse = from_binary(&res).unwrap();
+        assert!(value.maintenance);
+        assert_eq!(Some(1_600_000_000), value.eta);
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::ClearMaintenanceMode {},
+        )
+        .unwrap();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![3; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    }
+
+    #[test]
+    fn viewing_keys() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![9; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // without a viewing key the key can't be read
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::CreateViewingKey {
+            entropy: "some entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let key = match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("Must return the created viewing key"),
+        };
+
+        let msg = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: key.0.clone(),
+            owner: None,
+            label: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![9; 32])), value.reencryption_key);
+
+        // nobody else's viewing key opens it
+        set_viewing_key(deps.as_mut(), "anyone");
+        let msg = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: "anyone_key".to_string(),
+            owner: None,
+            label: None,
+        };
+        match query(deps.as_ref(), mock_env(), msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // only a salted hash is stored
+        let creator_raw = deps.api.addr_canonicalize("creator").unwrap();
+        let stored = read_viewing_key(&deps.storage, &creator_raw)
+            .unwrap()
+            .unwrap();
+        assert_ne!(Sha256::digest(key.as_bytes()).as_slice(), &stored.hash[..]);
+        assert_eq!(key.to_hashed(&stored.salt), stored.hash);
+
+        let msg = QueryMsg::GetViewingKeyInfo {
+            address: "creator".to_string(),
+            key: key.0.clone(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ViewingKeyInfoResponse = from_binary(&res).unwrap();
+        assert_eq!(mock_env().block.height, value.created_at);
+
+        // creating another key replaces the first, even with the same entropy
+        let mut env = mock_env();
+        env.block.height += 1;
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::CreateViewingKey {
+            entropy: "some entropy".to_string(),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let second = match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::CreateViewingKey { key } => key,
+            _ => panic!("Must return the created viewing key"),
+        };
+        assert_ne!(key, second);
+        let msg = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: key.0,
+            owner: None,
+            label: None,
+        };
+        assert_eq!(
+            Err(ContractError::Unauthorized {}),
+            query(deps.as_ref(), env.clone(), msg)
+        );
+        let msg = QueryMsg::GetViewingKeyInfo {
+            address: "creator".to_string(),
+            key: second.0,
+        };
+        let res = query(deps.as_ref(), env.clone(), msg).unwrap();
+        let value: ViewingKeyInfoResponse = from_binary(&res).unwrap();
+        assert_eq!(env.block.height, value.created_at);
+    }
+
+    #[test]
+    fn key_validation() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        let set = |reencryption_key: [u8; 32]| ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(reencryption_key.to_vec()),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let set_key_type = |key_type: KeyType| ExecuteMsg::SetKeyType { key_type };
+
+        // the reset sentinel is never a valid key
+        let info = mock_info("creator", &[]);
+        match execute(deps.as_mut(), mock_env(), info.clone(), set([0; 32])) {
+            Err(ContractError::InvalidKey { .. }) => {}
+            _ => panic!("Must return invalid key error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), set([1; 32])).unwrap();
+
+        let anyone = mock_info("anyone", &[]);
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            anyone,
+            set_key_type(KeyType::X25519),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_key_type(KeyType::X25519),
+        )
+        .unwrap();
+        let mut small_order = [0; 32];
+        small_order[0] = 1;
+        match execute(deps.as_mut(), mock_env(), info.clone(), set(small_order)) {
+            Err(ContractError::InvalidKey { .. }) => {}
+            _ => panic!("Must return invalid key error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), set([9; 32])).unwrap();
+
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_key_type(KeyType::Secp256k1),
+        )
+        .unwrap();
+        match execute(deps.as_mut(), mock_env(), info.clone(), set([0xff; 32])) {
+            Err(ContractError::InvalidKey { .. }) => {}
+            _ => panic!("Must return invalid key error"),
+        }
+        // the x coordinate of the generator
+        let mut generator = [0; 32];
+        generator.copy_from_slice(
+            &Binary::from_base64("eb5mfvncu6xVoGKVzocLBwKb/NstzijZWfKBWxb4F5g=").unwrap(),
+        );
+        let _res = execute(deps.as_mut(), mock_env(), info, set(generator)).unwrap();
+    }
+
+    #[test]
+    fn variable_length_keys() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let set = |len: usize| ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![8; len]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("creator", &[]);
+        let max = DEFAULT_MAX_KEY_LENGTH as usize;
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), set(max)).unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![8; max])), value.reencryption_key);
+        match execute(deps.as_mut(), mock_env(), info.clone(), set(max + 1)) {
+            Err(ContractError::InvalidKeyLength { len, .. }) => assert_eq!(max + 1, len),
+            _ => panic!("Must return invalid key length error"),
+        }
+
+        let msg = ExecuteMsg::SetMaxKeyLength {
+            max_key_length: 2048,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), info, set(max + 1)).unwrap();
+
+        // 32-byte keys sent or stored as arrays of numbers still read back
+        let legacy = format!(
+            r#"{{"set":{{"label":null,"reencryption_key":{:?},"expires_at":null}}}}"#,
+            [4u8; 32]
+        );
+        let msg: ExecuteMsg = cosmwasm_std::from_slice(legacy.as_bytes()).unwrap();
+        assert_eq!(
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![4; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+            msg
+        );
+        let legacy = format!(
+            r#"{{"reencryption_key":{:?},"version":1,"expires_at":null}}"#,
+            [5u8; 32]
+        );
+        let record: KeyRecord = cosmwasm_std::from_slice(legacy.as_bytes()).unwrap();
+        assert_eq!(Binary(vec![5; 32]), record.reencryption_key);
+    }
+
+    #[test]
+    fn single_use_retrieval_tokens() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        let env = mock_env();
+        let start = env.block.time.seconds();
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![5; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::SetDeadManSwitch {
+            inactivity_window: Some(100),
+            beneficiaries: vec!["heir".to_string()],
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let mint = |owner: Option<&str>| ExecuteMsg::MintRetrievalToken {
+            owner: owner.map(String::from),
+            label: None,
+            expires_at: Expiration::AtTime(start + 1000),
+        };
+        let minted = |res: Response| match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::MintRetrievalToken { token } => token,
+            _ => panic!("Must return the minted token"),
+        };
+        let redeem = |token: &str| ExecuteMsg::RedeemRetrievalToken {
+            token: token.to_string(),
+        };
+        let at = |time: u64| {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(time);
+            env
+        };
+
+        // the owner mints a token that anyone can redeem once
+        let res = execute(deps.as_mut(), env.clone(), info, mint(None)).unwrap();
+        let token = minted(res);
+        let info = mock_info("courier", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), redeem(&token)).unwrap();
+        match from_binary(&res.data.unwrap()).unwrap() {
+            ExecuteAnswer::RedeemRetrievalToken {
+                owner,
+                reencryption_key,
+                version,
+                ..
+            } => {
+                assert_eq!(Addr::unchecked("creator"), owner);
+                assert_eq!(Binary(vec![5; 32]), reencryption_key);
+                assert_eq!(1, version);
+            }
+            _ => panic!("Must return the key"),
+        }
+        match execute(deps.as_mut(), env.clone(), info, redeem(&token)) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        // a beneficiary can only mint once the switch has fired
+        let info = mock_info("heir", &[]);
+        match execute(deps.as_mut(), env, info.clone(), mint(Some("creator"))) {
+            Err(ContractError::NotReleased { .. }) => {}
+            _ => panic!("Must return not released error"),
+        }
+        let info_stranger = mock_info("stranger", &[]);
+        match execute(
+            deps.as_mut(),
+            at(start + 100),
+            info_stranger,
+            mint(Some("creator")),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let res = execute(deps.as_mut(), at(start + 100), info, mint(Some("creator"))).unwrap();
+        let token = minted(res);
+
+        // an expired token can't be redeemed
+        let info = mock_info("courier", &[]);
+        match execute(
+            deps.as_mut(),
+            at(start + 1000),
+            info.clone(),
+            redeem(&token),
+        ) {
+            Err(ContractError::TokenExpired {}) => {}
+            _ => panic!("Must return token expired error"),
+        }
+        let _res = execute(deps.as_mut(), at(start + 999), info, redeem(&token)).unwrap();
+    }
+
+    #[test]
+    fn migrate_addresses() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "anyone");
+
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![4; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let doc_hash = Binary(vec![12; 32]);
+        let msg = ExecuteMsg::StoreCiphertext {
+            doc_hash: doc_hash.clone(),
+            ciphertext: Binary(vec![1; 64]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg).unwrap();
+
+        // only the owner can run the migration
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::MigrateAddresses {
+            start: 0,
+            limit: None,
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // simulate an entry whose canonical form was derived under an older scheme
+        let stale = CanonicalAddr(Binary(vec![1; 20]));
+        let current = deps.api.addr_canonicalize("anyone").unwrap();
+        let label = DEFAULT_LABEL.as_bytes();
+        let record = keys_read(&deps.storage, &current).load(label).unwrap();
+        keys(&mut deps.storage, &current).remove(label);
+        keys(&mut deps.storage, &stale)
+            .save(label, &record)
+            .unwrap();
+        let owner_labels = labels_read(&deps.storage).load(current.as_slice()).unwrap();
+        labels(&mut deps.storage).remove(current.as_slice());
+        labels(&mut deps.storage)
+            .save(stale.as_slice(), &owner_labels)
+            .unwrap();
+        move_viewing_key(&mut deps.storage, &current, &stale).unwrap();
+        let mut stored = ciphertexts_read(&deps.storage, &current)
+            .load(doc_hash.as_slice())
+            .unwrap();
+        stored.owner.canonical = stale.clone();
+        ciphertexts(&mut deps.storage, &current).remove(doc_hash.as_slice());
+        ciphertexts(&mut deps.storage, &stale)
+            .save(doc_hash.as_slice(), &stored)
+            .unwrap();
+        let doc_hashes = ciphertext_hashes_read(&deps.storage)
+            .load(current.as_slice())
+            .unwrap();
+        ciphertext_hashes(&mut deps.storage).remove(current.as_slice());
+        ciphertext_hashes(&mut deps.storage)
+            .save(stale.as_slice(), &doc_hashes)
+            .unwrap();
+        let entry = activity_read(&deps.storage, &current)
+            .load(&0u64.to_be_bytes())
+            .unwrap();
+        activity(&mut deps.storage, &current).remove(&0u64.to_be_bytes());
+        activity(&mut deps.storage, &stale)
+            .save(&0u64.to_be_bytes(), &entry)
+            .unwrap();
+        activity_lens(&mut deps.storage).remove(current.as_slice());
+        activity_lens(&mut deps.storage)
+            .save(stale.as_slice(), &1)
+            .unwrap();
+        let stale_owner = StoredAddr {
+            canonical: stale.clone(),
+            human: Addr::unchecked("anyone"),
+        };
+        key_owners(&mut deps.storage)
+            .save(&0u64.to_be_bytes(), &stale_owner)
+            .unwrap();
+        assert!(query_key(deps.as_ref(), "anyone").is_err());
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::MigrateAddresses {
+            start: 0,
+            limit: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let value = query_key(deps.as_ref(), "anyone").unwrap();
+        assert_eq!(Some(Binary(vec![4; 32])), value.reencryption_key);
+        let key_owner = key_owners_read(&deps.storage)
+            .load(&0u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(current, key_owner.canonical);
+        let stored = ciphertexts_read(&deps.storage, &current)
+            .load(doc_hash.as_slice())
+            .unwrap();
+        assert_eq!(current, stored.owner.canonical);
+
+        // the history is left under the old address rather than copied
+        assert_eq!(
+            stale,
+            log_ids_read(&deps.storage)
+                .load(current.as_slice())
+                .unwrap()
+        );
+        let info = mock_info("anyone", &[]);
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![5; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msg = QueryMsg::GetKeyVersion {
+            address: "anyone".to_string(),
+            key: "anyone_key".to_string(),
+            owner: None,
+            label: None,
+            version: 1,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![4; 32])), value.reencryption_key);
+        let msg = QueryMsg::GetActivityStatement {
+            address: "anyone".to_string(),
+            key: "anyone_key".to_string(),
+            start_height: 0,
+            end_height: mock_env().block.height,
+            start: None,
+            limit: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ActivityStatementResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.entries.len());
+    }
+
+    #[test]
+    fn grant_and_revoke() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        set_viewing_key(deps.as_mut(), "grantee");
+
+        // a key has to be stored before it can be shared
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Grant {
+            grantee: "grantee".to_string(),
+        };
+        assert!(execute(deps.as_mut(), mock_env(), info, msg).is_err());
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![5; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let read_as_grantee = QueryMsg::GetReencryptionKey {
+            address: "grantee".to_string(),
+            key: "grantee_key".to_string(),
+            owner: Some("creator".to_string()),
+            label: None,
+        };
+        match query(deps.as_ref(), mock_env(), read_as_grantee.clone()) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Grant {
+            grantee: "grantee".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), read_as_grantee.clone()).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![5; 32])), value.reencryption_key);
+
+        let msg = QueryMsg::GetGrantees {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: GranteesResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![Addr::unchecked("grantee")], value.grantees);
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Revoke {
+            grantee: "grantee".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        match query(deps.as_ref(), mock_env(), read_as_grantee) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // revoking again reports the missing grant
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Revoke {
+            grantee: "grantee".to_string(),
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        // only the owner can propose a new one
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::TransferOwnership {
+            new_owner: "anyone".to_string(),
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::TransferOwnership {
+            new_owner: "successor".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // nothing changes until the new owner accepts
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOwner {}).unwrap();
+        let value: OwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(Addr::unchecked("creator"), value.owner);
+        assert_eq!(Some(Addr::unchecked("successor")), value.pending_owner);
+
+        let info = mock_info("anyone", &coins(2, "token"));
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("successor", &coins(2, "token"));
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::AcceptOwnership {},
+        )
+        .unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOwner {}).unwrap();
+        let value: OwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(Addr::unchecked("successor"), value.owner);
+        assert_eq!(None, value.pending_owner);
+
+        // the previous owner lost its rights
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::SetMaintenanceMode { eta: None };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+    }
+
+    #[test]
+    fn closed_set_mode() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        // only the owner can close the contract to new keys
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::SetSetMode {
+            mode: SetMode::Closed,
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::SetSetMode {
+            mode: SetMode::Closed,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let set_msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![6; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("setter", &coins(2, "token"));
+        match execute(deps.as_mut(), mock_env(), info, set_msg.clone()) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // the owner can always set
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, set_msg.clone()).unwrap();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::AddSetter {
+            address: "setter".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("setter", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, set_msg.clone()).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetSetters {}).unwrap();
+        let value: SettersResponse = from_binary(&res).unwrap();
+        assert_eq!(SetMode::Closed, value.mode);
+        assert_eq!(vec![Addr::unchecked("setter")], value.setters);
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::RemoveSetter {
+            address: "setter".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("setter", &coins(2, "token"));
+        assert!(execute(deps.as_mut(), mock_env(), info, set_msg.clone()).is_err());
+
+        // reopening lets anyone set again
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::SetSetMode {
+            mode: SetMode::Open,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let info = mock_info("setter", &coins(2, "token"));
+        let _res = execute(deps.as_mut(), mock_env(), info, set_msg).unwrap();
+    }
+
+    #[test]
+    fn key_versions() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        for key in 1..=3u8 {
+            let info = mock_info("creator", &coins(2, "token"));
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![key; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        let msg = QueryMsg::GetLatestVersion {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+            label: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: LatestVersionResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.version);
+
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![3; 32])), value.reencryption_key);
+        assert_eq!(3, value.version);
+
+        // earlier versions stay readable
+        for version in 1..=3u64 {
+            let msg = QueryMsg::GetKeyVersion {
+                address: "creator".to_string(),
+                key: "creator_key".to_string(),
+                owner: None,
+                label: None,
+                version,
+            };
+            let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+            let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+            assert_eq!(
+                Some(Binary(vec![version as u8; 32])),
+                value.reencryption_key
+            );
+            assert_eq!(version, value.version);
+        }
+
+        // a reset is a new version too
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Reset { label: None },
+        )
+        .unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![0; 32])), value.reencryption_key);
+        assert_eq!(4, value.version);
+
+        for version in [0u64, 5].iter() {
+            let msg = QueryMsg::GetKeyVersion {
+                address: "creator".to_string(),
+                key: "creator_key".to_string(),
+                owner: None,
+                label: None,
+                version: *version,
+            };
+            match query(deps.as_ref(), mock_env(), msg) {
+                Err(ContractError::Std(StdError::NotFound { .. })) => {}
+                _ => panic!("Must return not found error"),
+            }
+        }
+
+        // only the latest versions are archived, and Delete removes them all
+        let latest = MAX_KEY_VERSIONS + 2;
+        for key in 5..=latest {
+            let info = mock_info("creator", &coins(2, "token"));
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![key as u8; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let version = |deps: Deps, version: u64| {
+            let msg = QueryMsg::GetKeyVersion {
+                address: "creator".to_string(),
+                key: "creator_key".to_string(),
+                owner: None,
+                label: None,
+                version,
+            };
+            query(deps, mock_env(), msg)
+        };
+        match version(deps.as_ref(), 1) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let value: ReencryptionKeyResponse =
+            from_binary(&version(deps.as_ref(), 2).unwrap()).unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+
+        let info = mock_info("creator", &[]);
+        let msg = ExecuteMsg::Delete { label: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let creator_raw = deps.api.addr_canonicalize("creator").unwrap();
+        for archived in 2..latest {
+            assert!(
+                key_versions_read(&deps.storage, &creator_raw, DEFAULT_LABEL)
+                    .may_load(&archived.to_be_bytes())
+                    .unwrap()
+                    .is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn key_expiration() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let env = mock_env();
+        let info = mock_info("creator", &coins(2, "token"));
+        let expires_at = Expiration::AtHeight(env.block.height + 10);
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![1; 32]),
+            expires_at: Some(Expiration::AtHeight(env.block.height)),
+            description: None,
+            document_cid: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+        match res {
+            Err(ContractError::ExpiryInPast {}) => {}
+            _ => panic!("Must return error for an expiry in the past"),
+        }
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![1; 32]),
+            expires_at: Some(expires_at),
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(KeyStatus::Active, value.status);
+        assert_eq!(Some(Binary(vec![1; 32])), value.reencryption_key);
+        assert_eq!(Some(expires_at), value.expires_at);
+
+        // purging before expiry leaves the key alone
+        let msg = ExecuteMsg::Purge {
+            start: 0,
+            limit: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert!(query_key(deps.as_ref(), "creator").is_ok());
+
+        // from the expiry height on the key is withheld
+        let mut later = env;
+        later.block.height += 10;
+        let read_key = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+            label: None,
+        };
+        let res = query(deps.as_ref(), later.clone(), read_key).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(KeyStatus::Expired, value.status);
+        assert_eq!(None, value.reencryption_key);
+
+        // only the owner may purge
+        let anyone = mock_info("anyone", &[]);
+        match execute(deps.as_mut(), later.clone(), anyone, msg.clone()) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let res = execute(deps.as_mut(), later, info, msg).unwrap();
+        assert_eq!(attr("purged", "1"), res.attributes[1]);
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn dead_man_switch() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        set_viewing_key(deps.as_mut(), "heir");
+        set_viewing_key(deps.as_mut(), "stranger");
+
+        let env = mock_env();
+        let info = mock_info("creator", &coins(2, "token"));
+        let start = env.block.time.seconds();
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![7; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::SetDeadManSwitch {
+            inactivity_window: Some(100),
+            beneficiaries: vec!["heir".to_string()],
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+
+        let read_as_heir = QueryMsg::GetReencryptionKey {
+            address: "heir".to_string(),
+            key: "heir_key".to_string(),
+            owner: Some("creator".to_string()),
+            label: None,
+        };
+        let status_as = |address: &str| QueryMsg::GetReleaseStatus {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: Some("creator".to_string()),
+        };
+        let at = |time: u64| {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(time);
+            env
+        };
+
+        match query(deps.as_ref(), env, read_as_heir.clone()) {
+            Err(ContractError::NotReleased { deadline }) => assert_eq!(start + 100, deadline),
+            _ => panic!("Must return not released error"),
+        }
+
+        // a heartbeat pushes the deadline back
+        let _res = execute(
+            deps.as_mut(),
+            at(start + 60),
+            info,
+            ExecuteMsg::Heartbeat {},
+        )
+        .unwrap();
+        match query(deps.as_ref(), at(start + 120), read_as_heir.clone()) {
+            Err(ContractError::NotReleased { deadline }) => assert_eq!(start + 160, deadline),
+            _ => panic!("Must return not released error"),
+        }
+        let res = query(deps.as_ref(), at(start + 120), status_as("heir")).unwrap();
+        let value: ReleaseStatusResponse = from_binary(&res).unwrap();
+        assert!(!value.released);
+        assert_eq!(start + 160, value.deadline);
+        assert_eq!(40, value.remaining);
+
+        // after a missed heartbeat the beneficiary can read the key
+        let res = query(deps.as_ref(), at(start + 160), read_as_heir).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![7; 32])), value.reencryption_key);
+        let res = query(deps.as_ref(), at(start + 160), status_as("creator")).unwrap();
+        let value: ReleaseStatusResponse = from_binary(&res).unwrap();
+        assert!(value.released);
+        assert_eq!(0, value.remaining);
+
+        match query(deps.as_ref(), at(start + 160), status_as("stranger")) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let read_as_stranger = QueryMsg::GetReencryptionKey {
+            address: "stranger".to_string(),
+            key: "stranger_key".to_string(),
+            owner: Some("creator".to_string()),
+            label: None,
+        };
+        match query(deps.as_ref(), at(start + 160), read_as_stranger) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let info = mock_info("stranger", &[]);
+        match execute(deps.as_mut(), mock_env(), info, ExecuteMsg::Heartbeat {}) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn release_ceremony() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        for address in ["executor", "notary", "heir", "stranger"].iter() {
+            set_viewing_key(deps.as_mut(), address);
+        }
+
+        let env = mock_env();
+        let start = env.block.time.seconds();
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![3; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::SetCeremony {
+            participants: vec![
+                "executor".to_string(),
+                "notary".to_string(),
+                "heir".to_string(),
+            ],
+            window: 100,
+        };
+        let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+
+        let read_as = |address: &str| QueryMsg::GetReencryptionKey {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: Some("creator".to_string()),
+            label: None,
+        };
+        let check_in = |address: &str, time: u64| {
+            let mut env = mock_env();
+            env.block.time = Timestamp::from_seconds(time);
+            (
+                env,
+                mock_info(address, &[]),
+                ExecuteMsg::CheckIn {
+                    owner: "creator".to_string(),
+                },
+            )
+        };
+
+        match query(deps.as_ref(), mock_env(), read_as("heir")) {
+            Err(ContractError::CeremonyIncomplete {}) => {}
+            _ => panic!("Must return ceremony incomplete error"),
+        }
+        let (env, info, msg) = check_in("stranger", start);
+        match execute(deps.as_mut(), env, info, msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // the round lapses before the heir checks in, so it starts over
+        for &(address, time) in [("executor", start), ("notary", start + 50)].iter() {
+            let (env, info, msg) = check_in(address, time);
+            let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+        }
+        let (env, info, msg) = check_in("heir", start + 100);
+        let _res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let msg = QueryMsg::GetCeremonyStatus {
+            address: "notary".to_string(),
+            key: "notary_key".to_string(),
+            owner: Some("creator".to_string()),
+        };
+        let res = query(deps.as_ref(), env, msg).unwrap();
+        let value: CeremonyStatusResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(start + 100), value.round_started_at);
+        assert_eq!(vec![Addr::unchecked("heir")], value.checked_in);
+        match query(deps.as_ref(), mock_env(), read_as("heir")) {
+            Err(ContractError::CeremonyIncomplete {}) => {}
+            _ => panic!("Must return ceremony incomplete error"),
+        }
+
+        // everyone checks in within the window of the new round
+        for &(address, time) in [("executor", start + 150), ("notary", start + 199)].iter() {
+            let (env, info, msg) = check_in(address, time);
+            let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+        }
+        for &address in ["executor", "notary", "heir"].iter() {
+            let res = query(deps.as_ref(), mock_env(), read_as(address)).unwrap();
+            let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+            assert_eq!(Some(Binary(vec![3; 32])), value.reencryption_key);
+        }
+        match query(deps.as_ref(), mock_env(), read_as("stranger")) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+    }
+
+    #[test]
+    fn labelled_keys() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        for (label, key) in [(Some("will"), 1u8), (None, 2), (Some("medical-records"), 3)].iter() {
+            let info = mock_info("creator", &coins(2, "token"));
+            let msg = ExecuteMsg::Set {
+                label: label.map(String::from),
+                reencryption_key: Binary(vec![*key; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Set {
+            label: Some(String::new()),
+            reencryption_key: Binary(vec![4; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::InvalidLabel { .. }) => {}
+            _ => panic!("Must return error for an empty label"),
+        }
+
+        let msg = QueryMsg::ListLabels {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: LabelsResponse = from_binary(&res).unwrap();
+        assert_eq!(vec!["will", DEFAULT_LABEL, "medical-records"], value.labels);
+
+        let msg = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+            label: Some("will".to_string()),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![1; 32])), value.reencryption_key);
+        assert_eq!(1, value.version);
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+
+        // a reset only touches its own label
+        let info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Reset {
+            label: Some("will".to_string()),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![2; 32])), value.reencryption_key);
+        let msg = QueryMsg::GetReencryptionKey {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+            label: Some("unknown".to_string()),
+        };
+        match query(deps.as_ref(), mock_env(), msg) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        // several labels still make a single entry in the owner index
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1, state.key_owners);
+    }
+
+    #[test]
+    fn fragments() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        for address in ["proxy0", "proxy1", "proxy2", "stranger"].iter() {
+            set_viewing_key(deps.as_mut(), address);
+        }
+        let proxies: Vec<String> = (0..3).map(|index| format!("proxy{}", index)).collect();
+        let fragments: Vec<Binary> = (0..3u8).map(|index| Binary(vec![index; 8])).collect();
+
+        // fragments can only go to registered proxies
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::SetFragments {
+            fragments: fragments.clone(),
+            threshold: 2,
+            proxies: proxies.clone(),
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return error for unregistered proxies"),
+        }
+        for proxy in proxies.iter() {
+            let info = mock_info(proxy.as_str(), &[]);
+            let _res = execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::RegisterProxy {},
+            )
+            .unwrap();
+        }
+
+        let invalid = vec![
+            (fragments.clone(), 2, proxies[..2].to_vec()),
+            (fragments.clone(), 0, proxies.clone()),
+            (fragments.clone(), 4, proxies.clone()),
+            (
+                fragments.clone(),
+                2,
+                vec![proxies[0].clone(), proxies[1].clone(), proxies[0].clone()],
+            ),
+        ];
+        for (fragments, threshold, proxies) in invalid {
+            let info = mock_info("owner", &[]);
+            let msg = ExecuteMsg::SetFragments {
+                fragments,
+                threshold,
+                proxies,
+            };
+            match execute(deps.as_mut(), mock_env(), info, msg) {
+                Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+                _ => panic!("Must return error for an invalid fragment set"),
+            }
+        }
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::SetFragments {
+            fragments: fragments.clone(),
+            threshold: 2,
+            proxies: proxies.clone(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let state = config_read(&deps.storage).load().unwrap();
+        assert_eq!(1, state.key_owners);
+
+        let fragment_for = |address: &str| QueryMsg::GetFragment {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: "owner".to_string(),
+        };
+        for (index, proxy) in proxies.iter().enumerate() {
+            let res = query(deps.as_ref(), mock_env(), fragment_for(proxy.as_str())).unwrap();
+            let value: FragmentResponse = from_binary(&res).unwrap();
+            assert_eq!(index as u8, value.index);
+            assert_eq!(2, value.threshold);
+            assert_eq!(fragments[index], value.fragment);
+        }
+        match query(deps.as_ref(), mock_env(), fragment_for("stranger")) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        let info = mock_info("owner", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::ClearFragments {},
+        )
+        .unwrap();
+        match query(deps.as_ref(), mock_env(), fragment_for("proxy0")) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn proxy_registry() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        for address in ["owner", "proxy0", "proxy1", "proxy2"].iter() {
+            set_viewing_key(deps.as_mut(), address);
+            if address.starts_with("proxy") {
+                let info = mock_info(address, &[]);
+                let _res = execute(
+                    deps.as_mut(),
+                    mock_env(),
+                    info,
+                    ExecuteMsg::RegisterProxy {},
+                )
+                .unwrap();
+            }
+        }
+
+        let res = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ListProxies {
+                start: 1,
+                limit: None,
+            },
+        )
+        .unwrap();
+        let value: ProxiesResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            vec![Addr::unchecked("proxy1"), Addr::unchecked("proxy2")],
+            value.proxies
+        );
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::SetFragments {
+            fragments: vec![Binary(vec![0; 8]), Binary(vec![1; 8])],
+            threshold: 1,
+            proxies: vec!["proxy0".to_string(), "proxy1".to_string()],
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // a proxy may hold only one fragment of a set
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::AssignFragment {
+            index: 1,
+            proxy: "proxy0".to_string(),
+        };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return error for a proxy holding two fragments"),
+        }
+
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::AssignFragment {
+            index: 1,
+            proxy: "proxy2".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::UnassignFragment { index: 0 };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let info = mock_info("owner", &[]);
+        let msg = ExecuteMsg::UnassignFragment { index: 2 };
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+
+        let msg = QueryMsg::GetFragmentAssignments {
+            address: "owner".to_string(),
+            key: "owner_key".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: FragmentAssignmentsResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.threshold);
+        assert_eq!(
+            vec![None, Some(Addr::unchecked("proxy2"))],
+            value.assignments
+        );
+
+        let fragment_for = |address: &str| QueryMsg::GetFragment {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: "owner".to_string(),
+        };
+        match query(deps.as_ref(), mock_env(), fragment_for("proxy0")) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let res = query(deps.as_ref(), mock_env(), fragment_for("proxy2")).unwrap();
+        let value: FragmentResponse = from_binary(&res).unwrap();
+        assert_eq!(Binary(vec![1; 8]), value.fragment);
+
+        // a deregistered proxy keeps its assignment but can't read it
+        let info = mock_info("proxy2", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::DeregisterProxy {},
+        )
+        .unwrap();
+        match query(deps.as_ref(), mock_env(), fragment_for("proxy2")) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let info = mock_info("proxy2", &[]);
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::DeregisterProxy {},
+        ) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+    }
+
+    #[test]
+    fn migrate_checks_contract_name() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetContractVersion {}).unwrap();
+        let value: ContractVersion = from_binary(&res).unwrap();
+        assert_eq!(CONTRACT_NAME, value.contract);
+        assert_eq!(CONTRACT_VERSION, value.version);
+
+        // an older version of the same contract is brought up to date
+        contract_version(&mut deps.storage)
+            .save(&ContractVersion {
+                contract: CONTRACT_NAME.to_string(),
+                version: "0.0.1".to_string(),
+            })
+            .unwrap();
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(attr("from_version", "0.0.1"), res.attributes[1]);
+        let value = contract_version_read(&deps.storage).load().unwrap();
+        assert_eq!(CONTRACT_VERSION, value.version);
+
+        contract_version(&mut deps.storage)
+            .save(&ContractVersion {
+                contract: "another-contract".to_string(),
+                version: CONTRACT_VERSION.to_string(),
+            })
+            .unwrap();
+        match migrate(deps.as_mut(), mock_env(), MigrateMsg {}) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return error when migrating another contract"),
+        }
+    }
+
+    #[test]
+    fn responses_are_padded() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+
+        let msgs = vec![
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![1; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+            ExecuteMsg::Reset { label: None },
+            ExecuteMsg::CreateViewingKey {
+                entropy: "entropy".to_string(),
+            },
+        ];
+        for msg in msgs {
+            let info = mock_info("creator", &[]);
+            let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+            assert_eq!("action", res.attributes[0].key);
+            let log_len: usize = res
+                .attributes
+                .iter()
+                .map(|attribute| attribute.key.len() + attribute.value.len())
+                .sum();
+            assert_eq!(0, log_len % BLOCK_SIZE);
+            if let Some(data) = res.data {
+                assert_eq!(0, data.0.len() % BLOCK_SIZE);
+            }
+        }
+    }
+
+    #[test]
+    fn activity_statement() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            set_fee: Some(coin(10, "uscrt")),
+            ..InstantiateMsg::default()
+        };
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        set_viewing_key(deps.as_mut(), "owner");
+
+        let at = |height: u64| {
+            let mut env = mock_env();
+            env.block.height = height;
+            env
+        };
+        let info = mock_info("owner", &coins(10, "uscrt"));
+        let steps = vec![
+            (
+                100,
+                ExecuteMsg::Set {
+                    label: Some("will".to_string()),
+                    reencryption_key: Binary(vec![1; 32]),
+                    expires_at: None,
+                    description: None,
+                    document_cid: None,
+                },
+            ),
+            (
+                200,
+                ExecuteMsg::Grant {
+                    grantee: "lawyer".to_string(),
+                },
+            ),
+            (
+                300,
+                ExecuteMsg::Revoke {
+                    grantee: "lawyer".to_string(),
+                },
+            ),
+            (
+                400,
+                ExecuteMsg::Reset {
+                    label: Some("will".to_string()),
+                },
+            ),
+        ];
+        for (height, msg) in steps {
+            let _res = execute(deps.as_mut(), at(height), info.clone(), msg).unwrap();
+        }
+
+        let statement = |start_height: u64,
+                         end_height: u64,
+                         start: Option<u64>,
+                         limit: u32|
+         -> ActivityStatementResponse {
+            let msg = QueryMsg::GetActivityStatement {
+                address: "owner".to_string(),
+                key: "owner_key".to_string(),
+                start_height,
+                end_height,
+                start,
+                limit: Some(limit),
+            };
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap()
+        };
+
+        let all = statement(0, 1000, None, 10);
+        let actions: Vec<&str> = all.entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(vec!["set", "grant", "revoke", "reset"], actions);
+        assert_eq!(Some(coin(10, "uscrt")), all.entries[0].fee);
+        assert_eq!(Some(Addr::unchecked("lawyer")), all.entries[2].counterparty);
+        assert_eq!(None, all.next);
+
+        // the digest can be recomputed from the statement alone
+        let mut chain = all.previous_hash.clone();
+        for entry in all.entries.iter() {
+            chain = activity_hash(&chain, entry);
+            assert_eq!(entry.chain_hash, chain);
+        }
+        assert_eq!(all.digest, chain);
+
+        // a range is paged through with `next`, and pages chain onto each other
+        let first = statement(150, 400, None, 2);
+        let actions: Vec<&str> = first.entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(vec!["grant", "revoke"], actions);
+        assert_eq!(all.entries[0].chain_hash, first.previous_hash);
+        assert_eq!(Some(3), first.next);
+        let second = statement(150, 400, first.next, 2);
+        assert_eq!(first.digest, second.previous_hash);
+        assert_eq!(all.digest, second.digest);
+        assert_eq!(None, second.next);
+
+        // an empty range still commits to everything before it
+        let empty = statement(250, 299, None, 10);
+        assert!(empty.entries.is_empty());
+        assert_eq!(all.entries[1].chain_hash, empty.digest);
+
+        // statements are only for the owner
+        let msg = QueryMsg::GetActivityStatement {
+            address: "owner".to_string(),
+            key: "wrong_key".to_string(),
+            start_height: 0,
+            end_height: 1000,
+            start: None,
+            limit: None,
+        };
+        match query(deps.as_ref(), mock_env(), msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+    }
+
+    #[test]
+    fn runtime_configuration() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            max_keys_per_owner: Some(1),
+            default_inactivity_window: Some(600),
+            ..InstantiateMsg::default()
+        };
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let update = ExecuteMsg::UpdateConfig {
+            max_keys_per_owner: Some(0),
+            set_fee: Some(coin(5, "uscrt")),
+            default_inactivity_window: None,
+        };
+        let info = mock_info("anyone", &[]);
+        match execute(deps.as_mut(), mock_env(), info, update.clone()) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let info = mock_info("creator", &[]);
+        let _res = execute(deps.as_mut(), mock_env(), info, update).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            ConfigResponse {
+                max_keys_per_owner: None,
+                set_fee: Some(coin(5, "uscrt")),
+                key_type: KeyType::Any,
+                max_key_length: DEFAULT_MAX_KEY_LENGTH,
+                default_inactivity_window: Some(600),
+                token_set_fee: None,
+                set_mode: SetMode::Open,
+            },
+            value
+        );
+
+        // the new fee applies to the next Set, and the quota no longer does
+        let set = |label: &str| ExecuteMsg::Set {
+            label: Some(label.to_string()),
+            reencryption_key: Binary(vec![2; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("creator", &[]);
+        match execute(deps.as_mut(), mock_env(), info, set("will")) {
+            Err(ContractError::InsufficientFee { .. }) => {}
+            _ => panic!("Must return insufficient fee error"),
+        }
+        let info = mock_info("creator", &coins(5, "uscrt"));
+        for label in ["will", "deeds"].iter() {
+            let _res = execute(deps.as_mut(), mock_env(), info.clone(), set(label)).unwrap();
+        }
+
+        // zero amounts remove the fee and the default window
+        let update = ExecuteMsg::UpdateConfig {
+            max_keys_per_owner: None,
+            set_fee: Some(coin(0, "uscrt")),
+            default_inactivity_window: Some(0),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info, update).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(None, value.set_fee);
+        assert_eq!(None, value.default_inactivity_window);
+    }
+
+    #[test]
+    fn health() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        for owner in ["alice", "bob", "carol"].iter() {
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![4; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info(owner, &[]), msg).unwrap();
+        }
+        let health = |deps: Deps| -> HealthResponse {
+            from_binary(&query(deps, mock_env(), QueryMsg::Health {}).unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            HealthResponse {
+                paused: false,
+                frozen: false,
+                maintenance: false,
+                contract_version: CONTRACT_VERSION.to_string(),
+                last_purge_height: None,
+                purge_backlog: 3,
+                retirement_backlog: None,
+            },
+            health(deps.as_ref())
+        );
+
+        let mut env = mock_env();
+        env.block.height += 5;
+        let info = mock_info("creator", &[]);
+        let msg = ExecuteMsg::Purge {
+            start: 0,
+            limit: Some(2),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Pause {}).unwrap();
+
+        let value = health(deps.as_ref());
+        assert!(value.paused);
+        assert_eq!(Some(env.block.height), value.last_purge_height);
+        assert_eq!(1, value.purge_backlog);
+    }
+
+    #[test]
+    fn guardian_freeze() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![6; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // without a guardian nobody can freeze, not even the owner
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::Freeze {},
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let msg = ExecuteMsg::SetGuardian {
+            guardian: Some("trustee".to_string()),
+        };
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("trustee", &[]),
+            msg.clone(),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOwner {}).unwrap();
+        let value: OwnerResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Addr::unchecked("trustee")), value.guardian);
+
+        let trustee = mock_info("trustee", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            trustee.clone(),
+            ExecuteMsg::Freeze {},
+        )
+        .unwrap();
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Frozen {}) => {}
+            _ => panic!("Must return frozen error"),
+        }
+
+        // the guardian holds no other power, and the owner can't get around the freeze
+        let msg = ExecuteMsg::TransferOwnership {
+            new_owner: "trustee".to_string(),
+        };
+        match execute(deps.as_mut(), mock_env(), trustee.clone(), msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let msg = ExecuteMsg::SetGuardian { guardian: None };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::Frozen {}) => {}
+            _ => panic!("Must return frozen error"),
+        }
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::Unfreeze {},
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let _res = execute(deps.as_mut(), mock_env(), trustee, ExecuteMsg::Unfreeze {}).unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some(Binary(vec![6; 32])), value.reencryption_key);
+    }
+
+    #[test]
+    fn fee_withdrawal() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            set_fee: Some(coin(10, "uscrt")),
+            ..InstantiateMsg::default()
+        };
+        let _res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let paid = [coins(10, "uscrt"), coins(15, "uscrt")];
+        for (owner, funds) in ["alice", "bob"].iter().zip(paid.iter()) {
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![8; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info(owner, funds), msg).unwrap();
+        }
+        let collected = |deps: Deps| -> Vec<Coin> {
+            let res = query(deps, mock_env(), QueryMsg::GetCollectedFees {}).unwrap();
+            from_binary::<CollectedFeesResponse>(&res).unwrap().fees
+        };
+        assert_eq!(coins(25, "uscrt"), collected(deps.as_ref()));
+
+        // funds sent with other messages are kept as well
+        let msg = ExecuteMsg::CreateViewingKey {
+            entropy: "entropy".to_string(),
+        };
+        let info = mock_info("alice", &coins(3, "uscrt"));
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(coins(28, "uscrt"), collected(deps.as_ref()));
+
+        let withdraw = |amount: Option<Vec<Coin>>| ExecuteMsg::Withdraw {
+            amount,
+            recipient: None,
+        };
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            withdraw(None),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let too_much = withdraw(Some(coins(29, "uscrt")));
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            too_much,
+        ) {
+            Err(ContractError::ExceedsCollectedFees { available }) => {
+                assert_eq!(coin(28, "uscrt"), available)
+            }
+            _ => panic!("Must return exceeds collected fees error"),
+        }
+
+        let part = withdraw(Some(coins(5, "uscrt")));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), part).unwrap();
+        assert_eq!(
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "creator".to_string(),
+                amount: coins(5, "uscrt"),
+            })],
+            res.messages
+        );
+        let msg = ExecuteMsg::Withdraw {
+            amount: None,
+            recipient: Some("treasury".to_string()),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: coins(23, "uscrt"),
+            })],
+            res.messages
+        );
+        assert!(collected(deps.as_ref()).is_empty());
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            withdraw(None),
+        ) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return an error when there is nothing to withdraw"),
+        }
+    }
+
+    #[test]
+    fn token_payments() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            set_fee: Some(coin(10, "uscrt")),
+            ..InstantiateMsg::default()
+        };
+        let _res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        set_viewing_key(deps.as_mut(), "alice");
+
+        let msg = ExecuteMsg::SetTokenFee {
+            token_fee: Some(TokenFeeMsg {
+                token: "token".to_string(),
+                code_hash: "token_hash".to_string(),
+                amount: Uint128::new(100),
+            }),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(
+            vec![SubMsg::new(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                code_hash: "token_hash".to_string(),
+                msg: to_binary(&Snip20Msg::RegisterReceive {
+                    code_hash: mock_env().contract.code_hash,
+                })
+                .unwrap(),
+                funds: vec![],
+            })],
+            res.messages
+        );
+
+        let receive = |amount: u128| ExecuteMsg::Receive {
+            sender: "alice".to_string(),
+            from: "alice".to_string(),
+            amount: Uint128::new(amount),
+            memo: None,
+            msg: Some(to_binary(&ReceiveMsg::PayForSet {}).unwrap()),
+        };
+        // only the accepted token can credit payments
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("other", &[]),
+            receive(250),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive(99),
+        ) {
+            Err(ContractError::InsufficientFee { .. }) => {}
+            _ => panic!("Must return insufficient fee error"),
+        }
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive(250),
+        )
+        .unwrap();
+        let msg = QueryMsg::GetSetCredits {
+            address: "alice".to_string(),
+            key: "alice_key".to_string(),
+        };
+        let res = query(deps.as_ref(), mock_env(), msg.clone()).unwrap();
+        let value: SetCreditsResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.credits);
+
+        // Sets without the native fee use up credits, but not alongside a short native payment
+        let set = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![3; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let info = mock_info("alice", &coins(5, "uscrt"));
+        match execute(deps.as_mut(), mock_env(), info, set.clone()) {
+            Err(ContractError::InsufficientFee { required }) => {
+                assert_eq!(coin(10, "uscrt"), required)
+            }
+            _ => panic!("Must return insufficient fee error"),
+        }
+        for _ in 0..2 {
+            let info = mock_info("alice", &[]);
+            let _res = execute(deps.as_mut(), mock_env(), info, set.clone()).unwrap();
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), set) {
+            Err(ContractError::InsufficientFee { required }) => {
+                assert_eq!(coin(10, "uscrt"), required)
+            }
+            _ => panic!("Must return insufficient fee error"),
+        }
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: SetCreditsResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.credits);
+
+        // the token can't be swapped out while its fees are held, and the owner withdraws them
+        let msg = ExecuteMsg::SetTokenFee { token_fee: None };
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        ) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return an error while tokens are held"),
+        }
+        let withdraw = ExecuteMsg::WithdrawTokens {
+            amount: None,
+            recipient: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            withdraw,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![SubMsg::new(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                code_hash: "token_hash".to_string(),
+                msg: to_binary(&Snip20Msg::Transfer {
+                    recipient: "creator".to_string(),
+                    amount: Uint128::new(250),
+                })
+                .unwrap(),
+                funds: vec![],
+            })],
+            res.messages
+        );
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+
+        // nor while credits paid for in it are unused
+        let token_fee = ExecuteMsg::SetTokenFee {
+            token_fee: Some(TokenFeeMsg {
+                token: "token".to_string(),
+                code_hash: "token_hash".to_string(),
+                amount: Uint128::new(100),
+            }),
+        };
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            token_fee,
+        )
+        .unwrap();
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive(100),
+        )
+        .unwrap();
+        let withdraw = ExecuteMsg::WithdrawTokens {
+            amount: None,
+            recipient: None,
+        };
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            withdraw,
+        )
+        .unwrap();
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::Std(StdError::GenericErr { msg })) => {
+                assert!(msg.contains("credits"))
+            }
+            _ => panic!("Must return an error while credits are outstanding"),
+        }
+
+        // and no more can be bought once the contract is retiring
+        let retire = ExecuteMsg::Retire {
+            notice_period: MIN_RETIREMENT_NOTICE,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), retire).unwrap();
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("token", &[]),
+            receive(100),
+        ) {
+            Err(ContractError::Retiring {}) => {}
+            _ => panic!("Must return retiring error"),
+        }
+    }
+
+    #[test]
+    fn batch_set() {
+        let mut deps = mock_dependencies();
+
+        let msg = InstantiateMsg {
+            set_fee: Some(coin(10, "uscrt")),
+            ..InstantiateMsg::default()
+        };
+        let _res = instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        set_viewing_key(deps.as_mut(), "alice");
+        let entry = |label: &str, byte: u8| KeyEntry {
+            label: Some(label.to_string()),
+            reencryption_key: Binary(vec![byte; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+
+        // the fee is due for every entry
+        let msg = ExecuteMsg::BatchSet {
+            entries: vec![entry("default", 1), entry("will", 2)],
+        };
+        let info = mock_info("alice", &coins(10, "uscrt"));
+        match execute(deps.as_mut(), mock_env(), info, msg.clone()) {
+            Err(ContractError::InsufficientFee { required }) => {
+                assert_eq!(coin(20, "uscrt"), required)
+            }
+            _ => panic!("Must return insufficient fee error"),
+        }
+
+        // one bad entry rejects the whole batch
+        let bad = ExecuteMsg::BatchSet {
+            entries: vec![entry("default", 1), entry("", 2)],
+        };
+        let info = mock_info("alice", &coins(20, "uscrt"));
+        match execute(deps.as_mut(), mock_env(), info.clone(), bad) {
+            Err(ContractError::InvalidLabel { .. }) => {}
+            _ => panic!("Must return invalid label error"),
+        }
+        assert!(query_key(deps.as_ref(), "alice").is_err());
+
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            vec![
+                attr("action", "batch_set"),
+                attr("owner", "alice"),
+                attr("height", mock_env().block.height.to_string()),
+                attr("label", "default"),
+                attr("version", "1"),
+                attr("label", "will"),
+                attr("version", "1"),
+            ],
+            res.attributes[..7]
+        );
+        let value = query_key(deps.as_ref(), "alice").unwrap();
+        assert_eq!(Some(Binary(vec![1; 32])), value.reencryption_key);
+        let msg = QueryMsg::ListLabels {
+            address: "alice".to_string(),
+            key: "alice_key".to_string(),
+            owner: None,
+        };
+        let value: LabelsResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            vec!["default".to_string(), "will".to_string()],
+            value.labels
+        );
+
+        let msg = ExecuteMsg::BatchSet {
+            entries: vec![entry("will", 3), entry("will", 4)],
+        };
+        let info = mock_info("alice", &coins(20, "uscrt"));
+        match execute(deps.as_mut(), mock_env(), info, msg) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must reject a label given twice"),
+        }
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            ExecuteMsg::BatchSet { entries: vec![] },
+        ) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must reject an empty batch"),
+        }
+    }
+
+    #[test]
+    fn batch_revoke() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "owner");
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![7; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        for grantee in ["alice", "bob", "carol"].iter() {
+            let msg = ExecuteMsg::Grant {
+                grantee: grantee.to_string(),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        }
+        let grantees = |deps: Deps| -> Vec<Addr> {
+            let msg = QueryMsg::GetGrantees {
+                address: "owner".to_string(),
+                key: "owner_key".to_string(),
+            };
+            let value: GranteesResponse =
+                from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+            value.grantees
+        };
+
+        // a grant that doesn't exist keeps the others in place
+        let msg = ExecuteMsg::BatchRevoke {
+            grantees: vec!["alice".to_string(), "dave".to_string()],
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        assert_eq!(3, grantees(deps.as_ref()).len());
+
+        // a grantee listed twice is revoked once
+        let msg = ExecuteMsg::BatchRevoke {
+            grantees: vec![
+                "alice".to_string(),
+                "carol".to_string(),
+                "alice".to_string(),
+            ],
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert_eq!(
+            vec![
+                attr("action", "batch_revoke"),
+                attr("grantee", "alice"),
+                attr("grantee", "carol"),
+            ],
+            res.attributes[..3]
+        );
+        assert_eq!(vec![Addr::unchecked("bob")], grantees(deps.as_ref()));
+
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("owner", &[]),
+            ExecuteMsg::BatchRevoke { grantees: vec![] },
+        ) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must reject an empty batch"),
+        }
+    }
+
+    #[test]
+    fn delete() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+
+        let info = mock_info("creator", &[]);
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            ExecuteMsg::Delete { label: None },
+        ) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        for byte in [1, 2].iter() {
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![*byte; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        }
+
+        let msg = ExecuteMsg::Delete { label: None };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(attr("action", "delete"), res.attributes[0]);
+        assert_eq!(attr("label", DEFAULT_LABEL), res.attributes[2]);
+
+        match query_key(deps.as_ref(), "creator") {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let msg = QueryMsg::GetKeyVersion {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+            label: None,
+            version: 1,
+        };
+        assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+        let msg = QueryMsg::ListLabels {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            owner: None,
+        };
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: LabelsResponse = from_binary(&res).unwrap();
+        assert!(value.labels.is_empty());
+    }
+
+    #[test]
+    fn list_all_keys() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        set_viewing_key(deps.as_mut(), "alice");
+        for (owner, label) in [("alice", "will"), ("alice", "diary"), ("bob", "default")].iter() {
+            let msg = ExecuteMsg::Set {
+                label: Some(label.to_string()),
+                reencryption_key: Binary(vec![8; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info(owner, &[]), msg).unwrap();
+        }
+        let list = |deps: Deps, address: &str, start_after: Option<KeyCursor>| {
+            let msg = QueryMsg::ListAllKeys {
+                address: address.to_string(),
+                key: format!("{}_key", address),
+                start_after,
+                limit: Some(2),
+            };
+            query(deps, mock_env(), msg)
+        };
+
+        match list(deps.as_ref(), "alice", None) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let value: AllKeysResponse =
+            from_binary(&list(deps.as_ref(), "creator", None).unwrap()).unwrap();
+        assert_eq!(
+            vec![
+                KeyMetadata {
+                    owner: Addr::unchecked("alice"),
+                    label: "diary".to_string(),
+                    version: 1,
+                    expires_at: None,
+                    description: None,
+                    document_cid: None,
+                },
+                KeyMetadata {
+                    owner: Addr::unchecked("alice"),
+                    label: "will".to_string(),
+                    version: 1,
+                    expires_at: None,
+                    description: None,
+                    document_cid: None,
+                },
+            ],
+            value.keys
+        );
+        let next = value.next.unwrap();
+        assert_eq!(
+            KeyCursor {
+                owner_index: 0,
+                label: "will".to_string(),
+            },
+            next
+        );
+
+        let value: AllKeysResponse =
+            from_binary(&list(deps.as_ref(), "creator", Some(next)).unwrap()).unwrap();
+        assert_eq!(1, value.keys.len());
+        assert_eq!(Addr::unchecked("bob"), value.keys[0].owner);
+        assert_eq!(None, value.next);
+
+        // a zero limit still lists a key
+        let msg = QueryMsg::ListAllKeys {
+            address: "creator".to_string(),
+            key: "creator_key".to_string(),
+            start_after: None,
+            limit: Some(0),
+        };
+        let value: AllKeysResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, value.keys.len());
+
+        // owners without keys count towards the owners a page looks at
+        for index in 0..MAX_OWNERS_SCANNED {
+            let info = mock_info(&format!("former{}", index), &[]);
+            let msg = ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![8; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            };
+            let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+            let msg = ExecuteMsg::Delete { label: None };
+            let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        }
+        let msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![8; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("zed", &[]), msg).unwrap();
+        let start = KeyCursor {
+            owner_index: 2,
+            label: String::new(),
+        };
+        let value: AllKeysResponse =
+            from_binary(&list(deps.as_ref(), "creator", Some(start)).unwrap()).unwrap();
+        assert!(value.keys.is_empty());
+        let next = value.next.unwrap();
+        assert_eq!(
+            KeyCursor {
+                owner_index: 2 + MAX_OWNERS_SCANNED,
+                label: String::new(),
+            },
+            next
+        );
+        let value: AllKeysResponse =
+            from_binary(&list(deps.as_ref(), "creator", Some(next)).unwrap()).unwrap();
+        assert_eq!(1, value.keys.len());
+        assert_eq!(Addr::unchecked("zed"), value.keys[0].owner);
+        assert_eq!(None, value.next);
+    }
+
+    #[test]
+    fn stats_counters() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        let totals = |deps: Deps| -> Stats {
+            from_binary(&query(deps, mock_env(), QueryMsg::GetStats {}).unwrap()).unwrap()
+        };
+        assert_eq!(Stats::default(), totals(deps.as_ref()));
+
+        let mut env = mock_env();
+        let entry = |label: &str| KeyEntry {
+            label: Some(label.to_string()),
+            reencryption_key: Binary(vec![9; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let msg = ExecuteMsg::BatchSet {
+            entries: vec![entry("will"), entry("diary")],
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let msg = ExecuteMsg::BatchSet {
+            entries: vec![entry("will")],
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        // storing a new version of a key doesn't count it twice
+        let msg = ExecuteMsg::BatchSet {
+            entries: vec![entry("will")],
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        let msg = ExecuteMsg::Grant {
+            grantee: "carol".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(
+            Stats {
+                keys: 3,
+                owners: 2,
+                grants: 1,
+                last_updated: Some(env.block.height),
+                recount_next: None,
+            },
+            totals(deps.as_ref())
+        );
+
+        env.block.height += 1;
+        let msg = ExecuteMsg::Delete {
+            label: Some("will".to_string()),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+        let msg = ExecuteMsg::Revoke {
+            grantee: "carol".to_string(),
+        };
+        let _res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(
+            Stats {
+                keys: 2,
+                owners: 1,
+                grants: 0,
+                last_updated: Some(env.block.height),
+                recount_next: None,
+            },
+            totals(deps.as_ref())
+        );
+    }
+
+    #[test]
+    fn recount_stats() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        let set = |label: &str| ExecuteMsg::Set {
+            label: Some(label.to_string()),
+            reencryption_key: Binary(vec![14; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        for owner in ["alice", "bob"].iter() {
+            let _res = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(owner, &[]),
+                set("will"),
+            )
+            .unwrap();
+        }
+        // as if the keys had been stored by a version that kept no totals
+        stats(&mut deps.storage).remove();
+        let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        let totals = |deps: Deps| -> Stats {
+            from_binary(&query(deps, mock_env(), QueryMsg::GetStats {}).unwrap()).unwrap()
+        };
+        assert_eq!(Some(0), totals(deps.as_ref()).recount_next);
+
+        let recount = ExecuteMsg::RecountStats { limit: Some(1) };
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            recount.clone(),
+        ) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            recount.clone(),
+        )
+        .unwrap();
+        // bob is still to be counted, so his new key is left for the recount
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bob", &[]),
+            set("diary"),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::Grant {
+            grantee: "carol".to_string(),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        let value = totals(deps.as_ref());
+        assert_eq!(
+            (1, 1, 1, Some(1)),
+            (value.keys, value.owners, value.grants, value.recount_next)
+        );
+
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            recount.clone(),
+        )
+        .unwrap();
+        let value = totals(deps.as_ref());
+        assert_eq!(
+            (3, 2, 1, None),
+            (value.keys, value.owners, value.grants, value.recount_next)
+        );
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            recount,
+        ) {
+            Err(ContractError::Std(StdError::GenericErr { .. })) => {}
+            _ => panic!("Must return an error once counted"),
+        }
+    }
+
+    #[test]
+    fn key_metadata() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        let set = |description: &str, document_cid: &str| ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![10; 32]),
+            expires_at: None,
+            description: Some(description.to_string()),
+            document_cid: Some(document_cid.to_string()),
+        };
+
+        let info = mock_info("creator", &[]);
+        let msg = set(
+            "will",
+            "ipfs://QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG",
+        );
+        match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
+            Err(ContractError::InvalidMetadata { .. }) => {}
+            _ => panic!("Must return invalid metadata error"),
+        }
+        let msg = set(&"a".repeat(MAX_DESCRIPTION_LENGTH + 1), "bafkqaaa");
+        match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
+            Err(ContractError::InvalidMetadata { .. }) => {}
+            _ => panic!("Must return invalid metadata error"),
+        }
+
+        let msg = set("will", "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG");
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(Some("will".to_string()), value.description);
+        assert_eq!(
+            Some("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG".to_string()),
+            value.document_cid
+        );
+
+        // a reset key no longer describes the document
+        let info = mock_info("creator", &[]);
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            ExecuteMsg::Reset { label: None },
+        )
+        .unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(None, value.description);
+        assert_eq!(None, value.document_cid);
+    }
+
+    #[test]
+    fn store_capsule() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "creator");
+        let info = mock_info("creator", &[]);
+
+        let store = ExecuteMsg::StoreCapsule {
+            label: None,
+            capsule: Binary(vec![11; 98]),
+        };
+        match execute(deps.as_mut(), mock_env(), info.clone(), store.clone()) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let set = |byte: u8| ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![byte; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), set(1)).unwrap();
+        let msg = ExecuteMsg::StoreCapsule {
+            label: None,
+            capsule: Binary(vec![11; MAX_CAPSULE_LENGTH + 1]),
+        };
+        match execute(deps.as_mut(), mock_env(), info.clone(), msg) {
+            Err(ContractError::InvalidCapsule { .. }) => {}
+            _ => panic!("Must return invalid capsule error"),
+        }
+
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), store).unwrap();
+        assert_eq!(attr("version", "1"), res.attributes[3]);
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(1, value.version);
+        assert_eq!(Some(Binary(vec![11; 98])), value.capsule);
+
+        // a new key still applies to the same ciphertext
+        let _res = execute(deps.as_mut(), mock_env(), info, set(2)).unwrap();
+        let value = query_key(deps.as_ref(), "creator").unwrap();
+        assert_eq!(2, value.version);
+        assert_eq!(Some(Binary(vec![11; 98])), value.capsule);
+    }
+
+    #[test]
+    fn ciphertext_registry() {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info("creator", &[]);
+        let _res = instantiate(deps.as_mut(), mock_env(), info, InstantiateMsg::default()).unwrap();
+        set_viewing_key(deps.as_mut(), "alice");
+        set_viewing_key(deps.as_mut(), "bob");
+        let store = |ciphertext: Binary| ExecuteMsg::StoreCiphertext {
+            doc_hash: Binary(vec![12; 32]),
+            ciphertext,
+        };
+        let get = |address: &str, owner: &str| QueryMsg::GetCiphertext {
+            address: address.to_string(),
+            key: format!("{}_key", address),
+            owner: Some(owner.to_string()),
+            doc_hash: Binary(vec![12; 32]),
+        };
+
+        let msg = ExecuteMsg::StoreCiphertext {
+            doc_hash: Binary(vec![12; 20]),
+            ciphertext: Binary(vec![1; 64]),
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::InvalidDocHash {}) => {}
+            _ => panic!("Must return invalid doc hash error"),
+        }
+        let msg = store(Binary(vec![1; MAX_CIPHERTEXT_LENGTH + 1]));
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::InvalidCiphertextLength { .. }) => {}
+            _ => panic!("Must return invalid ciphertext length error"),
+        }
+
+        // bob storing under the same hash first doesn't stop alice
+        let msg = store(Binary(vec![2; 64]));
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg).unwrap();
+        let msg = store(Binary(vec![1; 64]));
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), get("alice", "alice")).unwrap();
+        let value: CiphertextResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            CiphertextResponse {
+                owner: Addr::unchecked("alice"),
+                ciphertext: Binary(vec![1; 64]),
+                block_height: mock_env().block.height,
+            },
+            value
+        );
+        let res = query(deps.as_ref(), mock_env(), get("bob", "bob")).unwrap();
+        let value: CiphertextResponse = from_binary(&res).unwrap();
+        assert_eq!(Binary(vec![2; 64]), value.ciphertext);
+
+        // reading someone else's takes the same permission as reading their key
+        match query(deps.as_ref(), mock_env(), get("bob", "alice")) {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        let delete = ExecuteMsg::DeleteCiphertext {
+            doc_hash: Binary(vec![12; 32]),
+        };
+        let _res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("alice", &[]),
+            delete.clone(),
+        )
+        .unwrap();
+        match query(deps.as_ref(), mock_env(), get("alice", "alice")) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), delete) {
+            Err(ContractError::Std(StdError::NotFound { .. })) => {}
+            _ => panic!("Must return not found error"),
+        }
+        let res = query(deps.as_ref(), mock_env(), get("bob", "bob")).unwrap();
+        let value: CiphertextResponse = from_binary(&res).unwrap();
+        assert_eq!(Binary(vec![2; 64]), value.ciphertext);
+
+        for byte in 0..MAX_CIPHERTEXTS {
+            let msg = ExecuteMsg::StoreCiphertext {
+                doc_hash: Binary(vec![byte as u8; 32]),
+                ciphertext: Binary(vec![1; 64]),
+            };
+            let _res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        }
+        let msg = ExecuteMsg::StoreCiphertext {
+            doc_hash: Binary(vec![MAX_CIPHERTEXTS as u8; 32]),
+            ciphertext: Binary(vec![1; 64]),
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::QuotaExceeded { .. }) => {}
+            _ => panic!("Must return quota exceeded error"),
+        }
+        // replacing a stored one doesn't count against the quota
+        let msg = ExecuteMsg::StoreCiphertext {
+            doc_hash: Binary(vec![0; 32]),
+            ciphertext: Binary(vec![3; 64]),
+        };
+        let _res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
     }
 }
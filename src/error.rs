@@ -0,0 +1,73 @@
+use cosmwasm_std::{Coin, StdError};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("contract is in maintenance mode{}", until(.eta))]
+    MaintenanceMode { eta: Option<u64> },
+
+    #[error("contract is paused")]
+    ContractPaused {},
+
+    #[error("key disclosure is frozen by the guardian")]
+    Frozen {},
+
+    #[error("contract is retiring, new keys can no longer be set")]
+    Retiring {},
+
+    #[error("label must be between 1 and {max} bytes long")]
+    InvalidLabel { max: usize },
+
+    #[error("expires_at must be in the future")]
+    ExpiryInPast {},
+
+    #[error("invalid reencryption key: {reason}")]
+    InvalidKey { reason: String },
+
+    #[error("key is {len} bytes long, at most {max} are allowed")]
+    InvalidKeyLength { len: usize, max: u32 },
+
+    #[error("invalid key metadata: {reason}")]
+    InvalidMetadata { reason: String },
+
+    #[error("capsule must be between 1 and {max} bytes long")]
+    InvalidCapsule { max: usize },
+
+    #[error("doc_hash must be a 32-byte sha256 hash")]
+    InvalidDocHash {},
+
+    #[error("ciphertext is {len} bytes long, at most {max} are allowed")]
+    InvalidCiphertextLength { len: usize, max: usize },
+
+    #[error("key has expired")]
+    KeyExpired {},
+
+    #[error("retrieval token has expired")]
+    TokenExpired {},
+
+    #[error("a fee of {required} is required")]
+    InsufficientFee { required: Coin },
+
+    #[error("only {available} has been collected")]
+    ExceedsCollectedFees { available: Coin },
+
+    #[error("at most {max} {what} can be stored")]
+    QuotaExceeded { what: String, max: u64 },
+
+    #[error("the release ceremony has not been completed")]
+    CeremonyIncomplete {},
+
+    /// The reader is a beneficiary of the owner's dead-man switch, but it has not fired yet.
+    #[error("key is not released until {deadline}")]
+    NotReleased { deadline: u64 },
+}
+
+fn until(eta: &Option<u64>) -> String {
+    eta.map(|eta| format!(" until {}", eta)).unwrap_or_default()
+}
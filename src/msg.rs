@@ -1,25 +1,581 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use cosmwasm_std::{Addr, Binary, Coin, Uint128};
+
+use crate::state::{
+    deserialize_key, ActivityEntry, Expiration, KeyType, SetMode, TokenFee, TransparencyEntry,
+};
+use crate::viewing_key::ViewingKey;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    // the contract owner, by default the sender
+    pub admin: Option<String>,
+    pub max_keys_per_owner: Option<u32>,
+    pub set_fee: Option<Coin>,
+    pub key_type: Option<KeyType>,
+    // used by SetDeadManSwitch when no inactivity window is given
+    pub default_inactivity_window: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct InitMsg {}
+pub struct MigrateMsg {}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
-pub enum HandleMsg {
-    Set { reencryption_key: [u8; 32] },
-    Reset {},
+pub enum ExecuteMsg {
+    // keys without a label are stored under the default label
+    Set {
+        label: Option<String>,
+        // earlier clients send 32-byte keys as arrays of numbers, which are still accepted
+        #[serde(deserialize_with = "deserialize_key")]
+        reencryption_key: Binary,
+        expires_at: Option<Expiration>,
+        // describe this version only; a later Set without them stores none
+        description: Option<String>,
+        document_cid: Option<String>,
+    },
+    // stores all of the entries or none of them, charging the Set fee for each
+    BatchSet {
+        entries: Vec<KeyEntry>,
+    },
+    Reset {
+        label: Option<String>,
+    },
+    // attaches the capsule to the stored key without making a new version of it
+    StoreCapsule {
+        label: Option<String>,
+        capsule: Binary,
+    },
+    // stores a small encrypted payload under the sha256 hash of its document, replacing the
+    // sender's earlier one for the same document
+    StoreCiphertext {
+        doc_hash: Binary,
+        ciphertext: Binary,
+    },
+    DeleteCiphertext {
+        doc_hash: Binary,
+    },
+    // removes the key and its archived versions instead of storing a zero key
+    Delete {
+        label: Option<String>,
+    },
+    Retire {
+        notice_period: u64,
+    },
+    CancelRetirement {},
+    PurgeRetired {},
+    SetMaintenanceMode {
+        eta: Option<u64>,
+    },
+    ClearMaintenanceMode {},
+    // stops Set, BatchSet, Grant, Reset, Delete, StoreCapsule, StoreCiphertext, DeleteCiphertext
+    // and minting retrieval tokens, as well as arming dead-man switches, ceremonies and
+    // fragments, until Unpause, e.g. while a suspected key compromise is dealt with
+    Pause {},
+    Unpause {},
+    // the guardian can't be replaced or removed while keys are frozen
+    SetGuardian {
+        guardian: Option<String>,
+    },
+    // guardian only: stops and resumes every query and redemption that discloses a key
+    Freeze {},
+    Unfreeze {},
+    CreateViewingKey {
+        entropy: String,
+    },
+    TransferOwnership {
+        new_owner: String,
+    },
+    AcceptOwnership {},
+    AddSetter {
+        address: String,
+    },
+    RemoveSetter {
+        address: String,
+    },
+    SetSetMode {
+        mode: SetMode,
+    },
+    // sends collected Set fees to `recipient` (by default the owner), all of them unless
+    // `amount` is given
+    Withdraw {
+        amount: Option<Vec<Coin>>,
+        recipient: Option<String>,
+    },
+    // sends collected tokens to `recipient` (by default the owner), all of them unless
+    // `amount` is given
+    WithdrawTokens {
+        amount: Option<Uint128>,
+        recipient: Option<String>,
+    },
+    // accepts payment for Sets in a SNIP-20 token, registering with it as a receiver; the
+    // token can only be changed once its collected fees are withdrawn
+    SetTokenFee {
+        token_fee: Option<TokenFeeMsg>,
+    },
+    // called by the token contract when tokens are sent here, with a ReceiveMsg as `msg`
+    Receive {
+        sender: String,
+        from: String,
+        amount: Uint128,
+        memo: Option<String>,
+        msg: Option<Binary>,
+    },
+    // changes only the given parameters; a zero quota, fee amount or inactivity window
+    // removes that limit, fee or default
+    UpdateConfig {
+        max_keys_per_owner: Option<u32>,
+        set_fee: Option<Coin>,
+        default_inactivity_window: Option<u64>,
+    },
+    // applies to keys set from now on; stored keys are not checked again
+    SetKeyType {
+        key_type: KeyType,
+    },
+    SetMaxKeyLength {
+        max_key_length: u32,
+    },
+    Grant {
+        grantee: String,
+    },
+    Revoke {
+        grantee: String,
+    },
+    // revokes all of the grants or, if any of them doesn't exist, none of them; a grantee listed
+    // more than once is revoked once
+    BatchRevoke {
+        grantees: Vec<String>,
+    },
+    // mints a single-use token for `owner`'s key (by default the sender's own), which the
+    // owner or, once their dead-man switch has fired, a beneficiary may do
+    MintRetrievalToken {
+        owner: Option<String>,
+        label: Option<String>,
+        expires_at: Expiration,
+    },
+    // returns the key the token was minted for and burns the token
+    RedeemRetrievalToken {
+        token: String,
+    },
+    // re-derives stored addresses for the key owner index entries in [start, start + limit)
+    MigrateAddresses {
+        start: u64,
+        limit: Option<u32>,
+    },
+    // arms or rearms the sender's dead-man switch, counting as a heartbeat
+    SetDeadManSwitch {
+        inactivity_window: Option<u64>,
+        beneficiaries: Vec<String>,
+    },
+    ClearDeadManSwitch {},
+    Heartbeat {},
+    // requires every participant to check in within `window` seconds of each other before
+    // they may read the sender's key
+    SetCeremony {
+        participants: Vec<String>,
+        window: u64,
+    },
+    ClearCeremony {},
+    // checks the sender in to `owner`'s ceremony, starting a new round if the last one lapsed
+    CheckIn {
+        owner: String,
+    },
+    // stores a key split into fragments, assigning `fragments[i]` to `proxies[i]`
+    SetFragments {
+        fragments: Vec<Binary>,
+        threshold: u8,
+        proxies: Vec<String>,
+    },
+    ClearFragments {},
+    // assigns the sender's fragment at `index` to a registered proxy
+    AssignFragment {
+        index: u8,
+        proxy: String,
+    },
+    UnassignFragment {
+        index: u8,
+    },
+    RegisterProxy {},
+    DeregisterProxy {},
+    // clears expired keys for the key owner index entries in [start, start + limit)
+    Purge {
+        start: u64,
+        limit: Option<u32>,
+    },
+    // counts up to `limit` more key owners into GetStats after a migration from a version
+    // that kept no totals
+    RecountStats {
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecuteAnswer {
+    CreateViewingKey {
+        key: ViewingKey,
+    },
+    MintRetrievalToken {
+        token: String,
+    },
+    RedeemRetrievalToken {
+        owner: Addr,
+        label: String,
+        reencryption_key: Binary,
+        version: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeyEntry {
+    pub label: Option<String>,
+    #[serde(deserialize_with = "deserialize_key")]
+    pub reencryption_key: Binary,
+    pub expires_at: Option<Expiration>,
+    pub description: Option<String>,
+    pub document_cid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenFeeMsg {
+    pub token: String,
+    pub code_hash: String,
+    pub amount: Uint128,
+}
+
+/// What tokens sent to the contract pay for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiveMsg {
+    // credits `from` with as many Sets as the amount pays for; any remainder is kept
+    PayForSet {},
+}
+
+/// The messages of a SNIP-20 token this contract sends.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Snip20Msg {
+    RegisterReceive { code_hash: String },
+    Transfer { recipient: String, amount: Uint128 },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum QueryMsg {
-    // GetCount returns the current count as a json-encoded number
-    GetReencryptionKey {},
+    // GetReencryptionKey returns the key stored by `owner` (by default `address` itself), which
+    // `address` must have been granted access to, authenticated with its viewing key
+    GetReencryptionKey {
+        address: String,
+        key: String,
+        owner: Option<String>,
+        label: Option<String>,
+    },
+    // only the latest MAX_KEY_VERSIONS archived versions are kept
+    GetKeyVersion {
+        address: String,
+        key: String,
+        owner: Option<String>,
+        label: Option<String>,
+        version: u64,
+    },
+    GetLatestVersion {
+        address: String,
+        key: String,
+        owner: Option<String>,
+        label: Option<String>,
+    },
+    ListLabels {
+        address: String,
+        key: String,
+        owner: Option<String>,
+    },
+    GetGrantees {
+        address: String,
+        key: String,
+    },
+    GetViewingKeyInfo {
+        address: String,
+        key: String,
+    },
+    GetSetCredits {
+        address: String,
+        key: String,
+    },
+    // GetActivityStatement returns `address`'s own activity in blocks [start_height,
+    // end_height], continuing from entry `start` when given
+    GetActivityStatement {
+        address: String,
+        key: String,
+        start_height: u64,
+        end_height: u64,
+        start: Option<u64>,
+        limit: Option<u32>,
+    },
+    // ListAllKeys is for the contract owner only, and never includes the keys themselves
+    ListAllKeys {
+        address: String,
+        key: String,
+        start_after: Option<KeyCursor>,
+        limit: Option<u32>,
+    },
+    GetReleaseStatus {
+        address: String,
+        key: String,
+        owner: Option<String>,
+    },
+    GetCeremonyStatus {
+        address: String,
+        key: String,
+        owner: Option<String>,
+    },
+    // GetFragment returns the fragment of `owner`'s set assigned to `address`
+    GetFragment {
+        address: String,
+        key: String,
+        owner: String,
+    },
+    GetFragmentAssignments {
+        address: String,
+        key: String,
+    },
+    // GetCiphertext is readable by whoever may read `owner`'s key, as it would otherwise
+    // tell anyone which documents `owner` has registered
+    GetCiphertext {
+        address: String,
+        key: String,
+        owner: Option<String>,
+        doc_hash: Binary,
+    },
+    ListProxies {
+        start: u64,
+        limit: Option<u32>,
+    },
+    GetContractVersion {},
+    GetStats {},
+    GetOwner {},
+    GetSetters {},
+    GetConfig {},
+    GetCollectedFees {},
+    GetRetirementStatus {},
+    GetMaintenanceStatus {},
+    // Health returns liveness data for monitoring, and nothing about any owner
+    Health {},
+    GetTransparencyHead {},
+    GetTransparencyEntries {
+        start: u64,
+        limit: Option<u32>,
+    },
+    // checkpoints are numbered from 0, one every `CHECKPOINT_INTERVAL` entries
+    GetTransparencyCheckpoints {
+        start: u64,
+        limit: Option<u32>,
+    },
 }
 
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ReencryptionKeyResponse {
-    pub reencryption_key: [u8; 32],
+    pub status: KeyStatus,
+    // withheld once the key has expired
+    pub reencryption_key: Option<Binary>,
+    pub version: u64,
+    pub expires_at: Option<Expiration>,
+    pub description: Option<String>,
+    pub document_cid: Option<String>,
+    pub capsule: Option<Binary>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CiphertextResponse {
+    pub owner: Addr,
+    pub ciphertext: Binary,
+    pub block_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FragmentResponse {
+    pub index: u8,
+    pub threshold: u8,
+    pub fragment: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FragmentAssignmentsResponse {
+    pub threshold: u8,
+    // the proxy holding each fragment, by fragment index
+    pub assignments: Vec<Option<Addr>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProxiesResponse {
+    pub proxies: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReleaseStatusResponse {
+    pub released: bool,
+    pub deadline: u64,
+    // seconds left before the beneficiaries gain access
+    pub remaining: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CeremonyStatusResponse {
+    pub participants: Vec<Addr>,
+    pub window: u64,
+    // unset when no round is running, including after one lapsed
+    pub round_started_at: Option<u64>,
+    pub checked_in: Vec<Addr>,
+    pub completed_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Active,
+    Expired,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LabelsResponse {
+    pub labels: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LatestVersionResponse {
+    pub version: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnerResponse {
+    pub owner: Addr,
+    pub pending_owner: Option<Addr>,
+    pub guardian: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SettersResponse {
+    pub mode: SetMode,
+    pub setters: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub max_keys_per_owner: Option<u32>,
+    pub set_fee: Option<Coin>,
+    pub key_type: KeyType,
+    pub max_key_length: u32,
+    pub default_inactivity_window: Option<u64>,
+    pub token_set_fee: Option<TokenFee>,
+    pub set_mode: SetMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CollectedFeesResponse {
+    pub fees: Vec<Coin>,
+    pub tokens: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SetCreditsResponse {
+    pub credits: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GranteesResponse {
+    pub grantees: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ViewingKeyInfoResponse {
+    // block height the viewing key was created at
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RetirementStage {
+    Active,
+    // remaining data may be purged once the block time passes `purge_after`
+    Retiring,
+    Purged,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RetirementStatusResponse {
+    pub stage: RetirementStage,
+    pub announced_at: Option<u64>,
+    pub purge_after: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MaintenanceStatusResponse {
+    pub maintenance: bool,
+    pub eta: Option<u64>,
+}
+
+/// `entries` are in the order they happened. Chaining them from `previous_hash` gives
+/// `digest`; `next` is the entry to continue from if the range holds more than one page.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActivityStatementResponse {
+    pub owner: Addr,
+    pub start_height: u64,
+    pub end_height: u64,
+    pub entries: Vec<ActivityEntry>,
+    pub previous_hash: Binary,
+    pub digest: Binary,
+    pub next: Option<u64>,
+}
+
+/// Where a listing of every stored key stopped: the label of the `owner_index`th key owner
+/// listed last. Labels are listed in order within each owner. An empty label resumes at the
+/// start of that owner's keys, which is where a page ends that ran out of owners to look at.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeyCursor {
+    pub owner_index: u64,
+    pub label: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeyMetadata {
+    pub owner: Addr,
+    pub label: String,
+    pub version: u64,
+    pub expires_at: Option<Expiration>,
+    pub description: Option<String>,
+    pub document_cid: Option<String>,
+}
+
+/// `next` is the `start_after` for the following page, or None once every key is listed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllKeysResponse {
+    pub keys: Vec<KeyMetadata>,
+    pub next: Option<KeyCursor>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HealthResponse {
+    pub paused: bool,
+    pub frozen: bool,
+    pub maintenance: bool,
+    pub contract_version: String,
+    // height of the last Purge, if there has been one
+    pub last_purge_height: Option<u64>,
+    // key owners the current Purge pass has yet to reach
+    pub purge_backlog: u64,
+    // key owners left to purge while the contract is retiring
+    pub retirement_backlog: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransparencyHeadResponse {
+    pub len: u64,
+    pub head: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransparencyEntriesResponse {
+    pub entries: Vec<TransparencyEntry>,
 }
@@ -1,21 +1,751 @@
+use std::fmt;
+
 use schemars::JsonSchema;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Storage};
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use cosmwasm_std::{Addr, Binary, BlockInfo, CanonicalAddr, Coin, StdResult, Storage, Uint128};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
+
+use crate::viewing_key::{ViewingKey, SALT_SIZE, VIEWING_KEY_SIZE};
 
+pub static CONTRACT_INFO_KEY: &[u8] = b"contract_info";
 pub static CONFIG_KEY: &[u8] = b"config";
+pub static CONTRACT_CONFIG_KEY: &[u8] = b"contract_config";
+pub static KEYS_KEY: &[u8] = b"keys";
+pub static LABELS_KEY: &[u8] = b"labels";
+pub static KEY_OWNERS_KEY: &[u8] = b"key_owners";
+pub static KEY_VERSIONS_KEY: &[u8] = b"key_versions";
+pub static GRANTS_KEY: &[u8] = b"grants";
+pub static RETRIEVAL_TOKENS_KEY: &[u8] = b"retrieval_tokens";
+pub static DEAD_MAN_SWITCHES_KEY: &[u8] = b"dead_man_switches";
+pub static CEREMONIES_KEY: &[u8] = b"ceremonies";
+pub static FRAGMENT_SETS_KEY: &[u8] = b"fragment_sets";
+pub static PROXIES_KEY: &[u8] = b"proxies";
+pub static PRNG_SEED_KEY: &[u8] = b"prng_seed";
+pub static VIEWING_KEYS_KEY: &[u8] = b"salted_viewing_keys";
+pub static TRANSPARENCY_HEAD_KEY: &[u8] = b"transparency_head";
+pub static TRANSPARENCY_ENTRIES_KEY: &[u8] = b"transparency_entries";
+pub static TRANSPARENCY_CHECKPOINTS_KEY: &[u8] = b"transparency_checkpoints";
+pub static PURGE_PROGRESS_KEY: &[u8] = b"purge_progress";
+pub static COLLECTED_FEES_KEY: &[u8] = b"collected_fees";
+pub static COLLECTED_TOKENS_KEY: &[u8] = b"collected_tokens";
+pub static SET_CREDITS_KEY: &[u8] = b"set_credits";
+pub static OUTSTANDING_CREDITS_KEY: &[u8] = b"outstanding_credits";
+pub static LOG_IDS_KEY: &[u8] = b"log_ids";
+pub static ACTIVITY_KEY: &[u8] = b"activity";
+pub static ACTIVITY_LENS_KEY: &[u8] = b"activity_lens";
+pub static STATS_KEY: &[u8] = b"stats";
+pub static STATS_COUNTED_KEY: &[u8] = b"stats_counted";
+pub static CIPHERTEXTS_KEY: &[u8] = b"ciphertexts";
+pub static CIPHERTEXT_HASHES_KEY: &[u8] = b"ciphertext_hashes";
+
+/// The longest key `Set` accepts until the owner changes it.
+pub const DEFAULT_MAX_KEY_LENGTH: u32 = 1024;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
-    pub reencryption_key: [u8;32],
     pub owner: CanonicalAddr,
+    pub owner_human: Addr,
+    // set by TransferOwnership until the new owner accepts
+    pub pending_owner: Option<StoredAddr>,
+    pub set_mode: SetMode,
+    // addresses besides the owner allowed to Set while `set_mode` is closed
+    pub setters: Vec<StoredAddr>,
+    // number of addresses in the key owner index
+    pub key_owners: u64,
+    pub retirement: Option<Retirement>,
+    pub maintenance: Option<Maintenance>,
+    // while set, keys can't be stored, reset or shared; older state has no such field
+    #[serde(default)]
+    pub paused: bool,
+    // may freeze key queries, and nothing else
+    pub guardian: Option<StoredAddr>,
+    // while set, no key is disclosed to anyone; only the guardian can lift it
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+/// Parameters the owner tunes, kept apart from the state the contract builds up.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    // labels each owner may store keys under, unlimited if unset
+    pub max_keys_per_owner: Option<u32>,
+    // fee that must be sent along with every Set
+    pub set_fee: Option<Coin>,
+    pub key_type: KeyType,
+    pub max_key_length: u32,
+    // inactivity window for dead-man switches armed without one
+    pub default_inactivity_window: Option<u64>,
+    // SNIP-20 token Sets can be paid for in instead of `set_fee`
+    pub token_set_fee: Option<TokenFee>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_keys_per_owner: None,
+            set_fee: None,
+            key_type: KeyType::Any,
+            max_key_length: DEFAULT_MAX_KEY_LENGTH,
+            default_inactivity_window: None,
+            token_set_fee: None,
+        }
+    }
+}
+
+/// A SNIP-20 token and the amount of it that pays for one `Set`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenFee {
+    pub token: Addr,
+    pub code_hash: String,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SetMode {
+    // anyone may store a key
+    Open,
+    // only the owner and the listed setters may store a key
+    Closed,
+}
+
+/// The kind of public key `Set` accepts. The all-zero key is never accepted, as it is what
+/// `Reset` stores in place of a key.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    #[default]
+    Any,
+    // a Montgomery u-coordinate, checked not to be of small order
+    X25519,
+    // a BIP-340 x-only public key
+    Secp256k1,
+}
+
+/// X25519 points of small order, with the unused top bit cleared, as listed by libsodium.
+const X25519_SMALL_ORDER: [[u8; 32]; 7] = [
+    [0; 32],
+    [
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0,
+    ],
+    [
+        0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4,
+        0x6a, 0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49,
+        0xb8, 0x00,
+    ],
+    [
+        0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef,
+        0x5b, 0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f,
+        0x11, 0x57,
+    ],
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+    [
+        0xee, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0x7f,
+    ],
+];
+
+impl KeyType {
+    /// Checks `key` is a usable key of this type, or says why it isn't.
+    pub fn validate(&self, key: &[u8]) -> Result<(), &'static str> {
+        if key.is_empty() {
+            return Err("the key is empty");
+        }
+        if key.iter().all(|byte| *byte == 0) {
+            return Err("the all-zero key is reserved for reset keys");
+        }
+        match self {
+            KeyType::Any => Ok(()),
+            KeyType::X25519 => {
+                if key.len() != 32 {
+                    return Err("X25519 public keys are 32 bytes long");
+                }
+                let mut point = [0u8; 32];
+                point.copy_from_slice(key);
+                point[31] &= 0x7f;
+                if X25519_SMALL_ORDER.contains(&point) {
+                    Err("not an X25519 public key: the point has small order")
+                } else {
+                    Ok(())
+                }
+            }
+            KeyType::Secp256k1 => k256::schnorr::VerifyingKey::from_bytes(key)
+                .map(|_| ())
+                .map_err(|_| "not a secp256k1 public key: no point has this x coordinate"),
+        }
+    }
+}
+
+/// Read-only maintenance mode. Queries keep working but every execute message is rejected
+/// until the owner clears it. `eta` is the block time the owner expects to be done by.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Maintenance {
+    pub eta: Option<u64>,
+}
+
+/// A retirement announced by the owner. New keys can't be set once this exists,
+/// and any remaining data may be purged once `purge_after` has passed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Retirement {
+    pub announced_at: u64,
+    pub purge_after: u64,
+    // position in the key owner index the purge has reached
+    pub purge_cursor: u64,
+    pub purged: bool,
+}
+
+/// An entry in the key owner index. The human form is kept so the canonical form can be
+/// derived again if the chain's address scheme changes, and vice versa.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StoredAddr {
+    pub canonical: CanonicalAddr,
+    pub human: Addr,
+}
+
+/// One labelled re-encryption key stored by an address. Every change to it, including a reset,
+/// archives the previous record and bumps `version`, which starts at 1.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KeyRecord {
+    #[serde(deserialize_with = "deserialize_key")]
+    pub reencryption_key: Binary,
+    pub version: u64,
+    pub expires_at: Option<Expiration>,
+    // what the owner says the key is for; records stored before it existed have none
+    #[serde(default)]
+    pub description: Option<String>,
+    // IPFS CID of the encrypted document or policy the key applies to
+    #[serde(default)]
+    pub document_cid: Option<String>,
+    // the capsule proxies need alongside the key; it belongs to the ciphertext rather than
+    // the key, so it is stored in place and carried over when a new key is Set
+    #[serde(default)]
+    pub capsule: Option<Binary>,
+}
+
+/// Reads a key given either as base64 or, as keys were written while they were always 32
+/// bytes long, as an array of byte values.
+pub fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Binary, D::Error> {
+    struct KeyVisitor;
+
+    impl<'de> Visitor<'de> for KeyVisitor {
+        type Value = Binary;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a base64 string or an array of bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Binary, E> {
+            Binary::from_base64(value).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Binary, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(32));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            Ok(Binary(bytes))
+        }
+    }
+
+    deserializer.deserialize_any(KeyVisitor)
+}
+
+/// Point after which a stored key is no longer served.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    AtHeight(u64),
+    // seconds since the unix epoch
+    AtTime(u64),
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match *self {
+            Expiration::AtHeight(height) => block.height >= height,
+            Expiration::AtTime(time) => block.time.seconds() >= time,
+        }
+    }
+}
+
+/// A re-encryption key split into fragments for threshold re-encryption. Any `threshold`
+/// of them are enough to re-encrypt, and each may only be read by the proxy holding it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FragmentSet {
+    pub threshold: u8,
+    pub fragments: Vec<Fragment>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Fragment {
+    pub fragment: Binary,
+    // a registered proxy; unassigned fragments can't be read by anyone
+    pub proxy: Option<StoredAddr>,
+}
+
+/// An owner's dead-man switch: once `inactivity_window` seconds pass without a heartbeat,
+/// the beneficiaries may read the owner's key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DeadManSwitch {
+    pub inactivity_window: u64,
+    pub last_heartbeat: u64,
+    pub beneficiaries: Vec<StoredAddr>,
+}
+
+impl DeadManSwitch {
+    pub fn deadline(&self) -> u64 {
+        self.last_heartbeat.saturating_add(self.inactivity_window)
+    }
+}
+
+/// An owner's release ceremony: once every participant has checked in within `window`
+/// seconds of the first check-in of a round, the participants may read the owner's key. A
+/// round that runs out of time starts over with the next check-in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Ceremony {
+    pub participants: Vec<StoredAddr>,
+    pub window: u64,
+    // block time of the first check-in of the current round
+    pub round_started_at: Option<u64>,
+    pub checked_in: Vec<StoredAddr>,
+    pub completed_at: Option<u64>,
+}
+
+impl Ceremony {
+    pub fn is_participant(&self, address: &CanonicalAddr) -> bool {
+        self.participants
+            .iter()
+            .any(|participant| &participant.canonical == address)
+    }
+
+    /// Whether the current round ran out of time before everyone checked in.
+    pub fn has_lapsed(&self, now: u64) -> bool {
+        self.completed_at.is_none()
+            && self
+                .round_started_at
+                .map_or(false, |started| now >= started.saturating_add(self.window))
+    }
+}
+
+/// A single-use token that reads one of `owner`'s keys, for parties without a viewing key.
+/// Stored under the sha256 of the token itself.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RetrievalToken {
+    pub owner: StoredAddr,
+    pub label: String,
+    pub expires_at: Expiration,
+}
+
+/// Where the last `Purge` of expired keys got to in the key owner index.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PurgeProgress {
+    pub last_height: u64,
+    pub next: u64,
+}
+
+/// A small encrypted payload `owner` stored under the hash of the document it belongs to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StoredCiphertext {
+    pub owner: StoredAddr,
+    pub ciphertext: Binary,
+    pub block_height: u64,
+}
+
+/// Running totals over the whole contract, kept up to date by every handler that stores or
+/// removes keys or grants. `owners` counts owners holding at least one key.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Stats {
+    pub keys: u64,
+    pub owners: u64,
+    pub grants: u64,
+    // height of the latest change to any key or grant
+    pub last_updated: Option<u64>,
+    // while set, owners from this index of the key owner index on are still to be counted,
+    // so the totals are short
+    #[serde(default)]
+    pub recount_next: Option<u64>,
+}
+
+/// Name and version of the code that last initialized or migrated the contract, as in cw2.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractVersion {
+    pub contract: String,
+    pub version: String,
+}
+
+/// Tip of the key-hash transparency log: the number of entries and the latest chain hash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransparencyHead {
+    pub len: u64,
+    pub head: Binary,
+}
+
+/// One change of the stored key. `chain_hash` commits to the previous entry's chain hash,
+/// so rewriting any entry breaks every hash after it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TransparencyEntry {
+    pub index: u64,
+    pub block_height: u64,
+    // sha256 of the canonical address whose key changed
+    pub owner_hash: Binary,
+    pub key_hash: Binary,
+    pub chain_hash: Binary,
+}
+
+/// One operation on an owner's keys or release settings. `chain_hash` commits to the
+/// owner's previous entry, so a statement can be checked against a later digest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ActivityEntry {
+    pub index: u64,
+    pub block_height: u64,
+    pub action: String,
+    pub label: Option<String>,
+    // the other party, e.g. a grantee or whoever redeemed a retrieval token
+    pub counterparty: Option<Addr>,
+    pub fee: Option<Coin>,
+    pub chain_hash: Binary,
+}
+
+/// A viewing key as stored: its salted hash, and the height it was created at so holders can
+/// tell when it is due for rotation.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StoredViewingKey {
+    pub salt: [u8; SALT_SIZE],
+    pub hash: [u8; VIEWING_KEY_SIZE],
+    pub created_at: u64,
+}
+
+/// Funds paid with `Set` that the owner has not withdrawn yet, one coin per denom.
+pub fn collected_fees(storage: &mut dyn Storage) -> Singleton<'_, Vec<Coin>> {
+    singleton(storage, COLLECTED_FEES_KEY)
+}
+
+pub fn collected_fees_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Vec<Coin>> {
+    singleton_read(storage, COLLECTED_FEES_KEY)
+}
+
+/// Tokens paid through `Receive` that the owner has not withdrawn yet.
+pub fn collected_tokens(storage: &mut dyn Storage) -> Singleton<'_, Uint128> {
+    singleton(storage, COLLECTED_TOKENS_KEY)
+}
+
+pub fn collected_tokens_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Uint128> {
+    singleton_read(storage, COLLECTED_TOKENS_KEY)
+}
+
+/// Sets each address has paid for in tokens and not used yet.
+pub fn set_credits(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, SET_CREDITS_KEY)
+}
+
+pub fn set_credits_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, SET_CREDITS_KEY)
+}
+
+/// Sum of every address's set credits.
+pub fn outstanding_credits(storage: &mut dyn Storage) -> Singleton<'_, u64> {
+    singleton(storage, OUTSTANDING_CREDITS_KEY)
+}
+
+pub fn outstanding_credits_read(storage: &dyn Storage) -> ReadonlySingleton<'_, u64> {
+    singleton_read(storage, OUTSTANDING_CREDITS_KEY)
+}
+
+pub fn stats(storage: &mut dyn Storage) -> Singleton<'_, Stats> {
+    singleton(storage, STATS_KEY)
+}
+
+pub fn stats_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Stats> {
+    singleton_read(storage, STATS_KEY)
+}
+
+/// Owners a recount has already counted into `Stats`.
+pub fn stats_counted(storage: &mut dyn Storage) -> Bucket<'_, bool> {
+    bucket(storage, STATS_COUNTED_KEY)
+}
+
+pub fn stats_counted_read(storage: &dyn Storage) -> ReadonlyBucket<'_, bool> {
+    bucket_read(storage, STATS_COUNTED_KEY)
+}
+
+pub fn purge_progress(storage: &mut dyn Storage) -> Singleton<'_, PurgeProgress> {
+    singleton(storage, PURGE_PROGRESS_KEY)
+}
+
+pub fn purge_progress_read(storage: &dyn Storage) -> ReadonlySingleton<'_, PurgeProgress> {
+    singleton_read(storage, PURGE_PROGRESS_KEY)
+}
+
+pub fn contract_version(storage: &mut dyn Storage) -> Singleton<'_, ContractVersion> {
+    singleton(storage, CONTRACT_INFO_KEY)
 }
 
-pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
+pub fn contract_version_read(storage: &dyn Storage) -> ReadonlySingleton<'_, ContractVersion> {
+    singleton_read(storage, CONTRACT_INFO_KEY)
+}
+
+pub fn config(storage: &mut dyn Storage) -> Singleton<'_, State> {
     singleton(storage, CONFIG_KEY)
 }
 
-pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
+pub fn config_read(storage: &dyn Storage) -> ReadonlySingleton<'_, State> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+pub fn contract_config(storage: &mut dyn Storage) -> Singleton<'_, Config> {
+    singleton(storage, CONTRACT_CONFIG_KEY)
+}
+
+pub fn contract_config_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Config> {
+    singleton_read(storage, CONTRACT_CONFIG_KEY)
+}
+
+/// The keys stored by `owner`, by label.
+pub fn keys<'a>(storage: &'a mut dyn Storage, owner: &CanonicalAddr) -> Bucket<'a, KeyRecord> {
+    Bucket::multilevel(storage, &[KEYS_KEY, owner.as_slice()])
+}
+
+pub fn keys_read<'a>(
+    storage: &'a dyn Storage,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, KeyRecord> {
+    ReadonlyBucket::multilevel(storage, &[KEYS_KEY, owner.as_slice()])
+}
+
+/// Every label `owner` has a key under, in the order they were first set. The entry stays
+/// (possibly empty) once written, so it also tells whether `owner` is in the owner index.
+pub fn labels(storage: &mut dyn Storage) -> Bucket<'_, Vec<String>> {
+    bucket(storage, LABELS_KEY)
+}
+
+pub fn labels_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Vec<String>> {
+    bucket_read(storage, LABELS_KEY)
+}
+
+/// Previous versions of `owner`'s key under `label`, keyed by version number.
+pub fn key_versions<'a>(
+    storage: &'a mut dyn Storage,
+    owner: &CanonicalAddr,
+    label: &str,
+) -> Bucket<'a, KeyRecord> {
+    Bucket::multilevel(
+        storage,
+        &[KEY_VERSIONS_KEY, owner.as_slice(), label.as_bytes()],
+    )
+}
+
+pub fn key_versions_read<'a>(
+    storage: &'a dyn Storage,
+    owner: &CanonicalAddr,
+    label: &str,
+) -> ReadonlyBucket<'a, KeyRecord> {
+    ReadonlyBucket::multilevel(
+        storage,
+        &[KEY_VERSIONS_KEY, owner.as_slice(), label.as_bytes()],
+    )
+}
+
+/// Every address that has ever stored a key, by the order they first did so. Secret
+/// contracts can't iterate storage, so this is what contract-wide passes walk over.
+pub fn key_owners(storage: &mut dyn Storage) -> Bucket<'_, StoredAddr> {
+    bucket(storage, KEY_OWNERS_KEY)
+}
+
+pub fn key_owners_read(storage: &dyn Storage) -> ReadonlyBucket<'_, StoredAddr> {
+    bucket_read(storage, KEY_OWNERS_KEY)
+}
+
+/// The addresses a key owner has allowed to read their key.
+pub fn grants(storage: &mut dyn Storage) -> Bucket<'_, Vec<StoredAddr>> {
+    bucket(storage, GRANTS_KEY)
+}
+
+pub fn grants_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Vec<StoredAddr>> {
+    bucket_read(storage, GRANTS_KEY)
+}
+
+pub fn retrieval_tokens(storage: &mut dyn Storage) -> Bucket<'_, RetrievalToken> {
+    bucket(storage, RETRIEVAL_TOKENS_KEY)
+}
+
+pub fn retrieval_tokens_read(storage: &dyn Storage) -> ReadonlyBucket<'_, RetrievalToken> {
+    bucket_read(storage, RETRIEVAL_TOKENS_KEY)
+}
+
+pub fn dead_man_switches(storage: &mut dyn Storage) -> Bucket<'_, DeadManSwitch> {
+    bucket(storage, DEAD_MAN_SWITCHES_KEY)
+}
+
+pub fn dead_man_switches_read(storage: &dyn Storage) -> ReadonlyBucket<'_, DeadManSwitch> {
+    bucket_read(storage, DEAD_MAN_SWITCHES_KEY)
+}
+
+pub fn ceremonies(storage: &mut dyn Storage) -> Bucket<'_, Ceremony> {
+    bucket(storage, CEREMONIES_KEY)
+}
+
+pub fn ceremonies_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Ceremony> {
+    bucket_read(storage, CEREMONIES_KEY)
+}
+
+pub fn fragment_sets(storage: &mut dyn Storage) -> Bucket<'_, FragmentSet> {
+    bucket(storage, FRAGMENT_SETS_KEY)
+}
+
+pub fn fragment_sets_read(storage: &dyn Storage) -> ReadonlyBucket<'_, FragmentSet> {
+    bucket_read(storage, FRAGMENT_SETS_KEY)
+}
+
+/// The ciphertexts stored by `owner`, by document hash.
+pub fn ciphertexts<'a>(
+    storage: &'a mut dyn Storage,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, StoredCiphertext> {
+    Bucket::multilevel(storage, &[CIPHERTEXTS_KEY, owner.as_slice()])
+}
+
+pub fn ciphertexts_read<'a>(
+    storage: &'a dyn Storage,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, StoredCiphertext> {
+    ReadonlyBucket::multilevel(storage, &[CIPHERTEXTS_KEY, owner.as_slice()])
+}
+
+/// Every document hash `owner` has a ciphertext stored under, so purges and migrations
+/// can find them.
+pub fn ciphertext_hashes(storage: &mut dyn Storage) -> Bucket<'_, Vec<Binary>> {
+    bucket(storage, CIPHERTEXT_HASHES_KEY)
+}
+
+pub fn ciphertext_hashes_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Vec<Binary>> {
+    bucket_read(storage, CIPHERTEXT_HASHES_KEY)
+}
+
+/// Registered re-encryption proxies, in the order they registered.
+pub fn proxies(storage: &mut dyn Storage) -> Singleton<'_, Vec<StoredAddr>> {
+    singleton(storage, PROXIES_KEY)
+}
+
+pub fn proxies_read(storage: &dyn Storage) -> ReadonlySingleton<'_, Vec<StoredAddr>> {
+    singleton_read(storage, PROXIES_KEY)
+}
+
+pub fn write_prng_seed(storage: &mut dyn Storage, seed: &[u8]) {
+    storage.set(PRNG_SEED_KEY, seed)
+}
+
+pub fn read_prng_seed(storage: &dyn Storage) -> Vec<u8> {
+    storage.get(PRNG_SEED_KEY).unwrap_or_default()
+}
+
+pub fn write_viewing_key(
+    storage: &mut dyn Storage,
+    owner: &CanonicalAddr,
+    key: &ViewingKey,
+    salt: [u8; SALT_SIZE],
+    created_at: u64,
+) -> StdResult<()> {
+    let stored = StoredViewingKey {
+        salt,
+        hash: key.to_hashed(&salt),
+        created_at,
+    };
+    bucket(storage, VIEWING_KEYS_KEY).save(owner.as_slice(), &stored)
+}
+
+pub fn read_viewing_key(
+    storage: &dyn Storage,
+    owner: &CanonicalAddr,
+) -> StdResult<Option<StoredViewingKey>> {
+    bucket_read(storage, VIEWING_KEYS_KEY).may_load(owner.as_slice())
+}
+
+pub fn move_viewing_key(
+    storage: &mut dyn Storage,
+    from: &CanonicalAddr,
+    to: &CanonicalAddr,
+) -> StdResult<()> {
+    let mut viewing_keys: Bucket<StoredViewingKey> = bucket(storage, VIEWING_KEYS_KEY);
+    if let Some(stored) = viewing_keys.may_load(from.as_slice())? {
+        viewing_keys.save(to.as_slice(), &stored)?;
+        viewing_keys.remove(from.as_slice());
+    }
+    Ok(())
+}
+
+pub fn transparency_head(storage: &mut dyn Storage) -> Singleton<'_, TransparencyHead> {
+    singleton(storage, TRANSPARENCY_HEAD_KEY)
+}
+
+pub fn transparency_head_read(storage: &dyn Storage) -> ReadonlySingleton<'_, TransparencyHead> {
+    singleton_read(storage, TRANSPARENCY_HEAD_KEY)
+}
+
+pub fn transparency_entries(storage: &mut dyn Storage) -> Bucket<'_, TransparencyEntry> {
+    bucket(storage, TRANSPARENCY_ENTRIES_KEY)
+}
+
+pub fn transparency_entries_read(storage: &dyn Storage) -> ReadonlyBucket<'_, TransparencyEntry> {
+    bucket_read(storage, TRANSPARENCY_ENTRIES_KEY)
+}
+
+pub fn transparency_checkpoints(storage: &mut dyn Storage) -> Bucket<'_, TransparencyEntry> {
+    bucket(storage, TRANSPARENCY_CHECKPOINTS_KEY)
+}
+
+pub fn transparency_checkpoints_read(
+    storage: &dyn Storage,
+) -> ReadonlyBucket<'_, TransparencyEntry> {
+    bucket_read(storage, TRANSPARENCY_CHECKPOINTS_KEY)
+}
+
+/// The canonical address an owner's key versions and activity log are kept under, recorded
+/// only for owners whose canonical address has changed since. Those histories can grow
+/// without bound, so `MigrateAddresses` points the new address at them instead of moving them.
+pub fn log_ids(storage: &mut dyn Storage) -> Bucket<'_, CanonicalAddr> {
+    bucket(storage, LOG_IDS_KEY)
+}
+
+pub fn log_ids_read(storage: &dyn Storage) -> ReadonlyBucket<'_, CanonicalAddr> {
+    bucket_read(storage, LOG_IDS_KEY)
+}
+
+/// `owner`'s activity log, keyed by entry index.
+pub fn activity<'a>(
+    storage: &'a mut dyn Storage,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, ActivityEntry> {
+    Bucket::multilevel(storage, &[ACTIVITY_KEY, owner.as_slice()])
+}
+
+pub fn activity_read<'a>(
+    storage: &'a dyn Storage,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, ActivityEntry> {
+    ReadonlyBucket::multilevel(storage, &[ACTIVITY_KEY, owner.as_slice()])
+}
+
+/// Number of entries in each owner's activity log.
+pub fn activity_lens(storage: &mut dyn Storage) -> Bucket<'_, u64> {
+    bucket(storage, ACTIVITY_LENS_KEY)
+}
+
+pub fn activity_lens_read(storage: &dyn Storage) -> ReadonlyBucket<'_, u64> {
+    bucket_read(storage, ACTIVITY_LENS_KEY)
+}
@@ -0,0 +1,288 @@
+//! Deterministic replay vectors for the current message schema.
+//!
+//! `generate` runs a fixed sequence of messages against an in-memory store and records
+//! the hash of the complete contract storage after each one. Alternate client
+//! implementations, or a future version of this contract after a migration, can replay
+//! the same messages and compare hashes with `verify`.
+//!
+//! This is built on `cosmwasm_std::testing`, so it is only available in native builds.
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use cosmwasm_std::testing::{mock_env, mock_info, MockApi, MockQuerier};
+use cosmwasm_std::{Binary, Env, MessageInfo, OwnedDeps, Storage, Timestamp};
+
+use crate::contract::{execute, instantiate};
+use crate::msg::{ExecuteMsg, InstantiateMsg};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TestVectors {
+    pub init_env: Env,
+    pub init_info: MessageInfo,
+    pub init_msg: InstantiateMsg,
+    pub init_state_hash: Binary,
+    pub steps: Vec<TestVector>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TestVector {
+    pub env: Env,
+    pub info: MessageInfo,
+    pub msg: ExecuteMsg,
+    pub success: bool,
+    // hash of the whole contract storage after the message was executed
+    pub state_hash: Binary,
+}
+
+/// A storage with a deterministic iteration order, so its contents can be hashed.
+#[derive(Default, Clone)]
+pub struct VectorStorage(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl VectorStorage {
+    pub fn state_hash(&self) -> Binary {
+        let mut hasher = Sha256::new();
+        for (key, value) in self.0.iter() {
+            hasher.update((key.len() as u64).to_be_bytes());
+            hasher.update(key);
+            hasher.update((value.len() as u64).to_be_bytes());
+            hasher.update(value);
+        }
+        Binary(hasher.finalize().to_vec())
+    }
+}
+
+impl Storage for VectorStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.0.remove(key);
+    }
+}
+
+type VectorDeps = OwnedDeps<VectorStorage, MockApi, MockQuerier>;
+
+fn vector_dependencies() -> VectorDeps {
+    OwnedDeps {
+        storage: VectorStorage::default(),
+        api: MockApi::default(),
+        querier: MockQuerier::new(&[]),
+        custom_query_type: PhantomData,
+    }
+}
+
+fn vector_env(step: u64) -> Env {
+    let mut env = mock_env();
+    env.block.height = 12_345 + step;
+    env.block.time = Timestamp::from_seconds(1_571_797_419 + 5 * step);
+    env
+}
+
+/// Executes `msg`, discarding its writes if it fails the same way a reverted transaction would.
+fn replay(deps: &mut VectorDeps, env: Env, info: MessageInfo, msg: ExecuteMsg) -> bool {
+    let snapshot = deps.storage.clone();
+    let success = execute(deps.as_mut(), env, info, msg).is_ok();
+    if !success {
+        deps.storage = snapshot;
+    }
+    success
+}
+
+/// The canonical message sequence. Failing messages are part of it on purpose: they
+/// must leave the state untouched.
+fn canonical_sequence() -> Vec<(&'static str, ExecuteMsg)> {
+    vec![
+        (
+            "alice",
+            ExecuteMsg::CreateViewingKey {
+                entropy: "alice's entropy".to_string(),
+            },
+        ),
+        (
+            "alice",
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![1; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+        ),
+        (
+            "bob",
+            ExecuteMsg::CreateViewingKey {
+                entropy: "bob's entropy".to_string(),
+            },
+        ),
+        (
+            "bob",
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![2; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+        ),
+        (
+            "alice",
+            ExecuteMsg::Grant {
+                grantee: "bob".to_string(),
+            },
+        ),
+        (
+            "alice",
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![3; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+        ),
+        ("carol", ExecuteMsg::Reset { label: None }),
+        ("bob", ExecuteMsg::Reset { label: None }),
+        (
+            "alice",
+            ExecuteMsg::Revoke {
+                grantee: "bob".to_string(),
+            },
+        ),
+        (
+            "bob",
+            ExecuteMsg::TransferOwnership {
+                new_owner: "bob".to_string(),
+            },
+        ),
+        (
+            "creator",
+            ExecuteMsg::TransferOwnership {
+                new_owner: "carol".to_string(),
+            },
+        ),
+        ("carol", ExecuteMsg::AcceptOwnership {}),
+        (
+            "carol",
+            ExecuteMsg::SetMaintenanceMode {
+                eta: Some(1_571_800_000),
+            },
+        ),
+        (
+            "alice",
+            ExecuteMsg::Set {
+                label: None,
+                reencryption_key: Binary(vec![4; 32]),
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+        ),
+        ("carol", ExecuteMsg::ClearMaintenanceMode {}),
+    ]
+}
+
+/// Runs the canonical sequence and records the resulting vectors.
+pub fn generate() -> TestVectors {
+    let mut deps = vector_dependencies();
+
+    let init_env = vector_env(0);
+    let init_info = mock_info("creator", &[]);
+    let init_msg = InstantiateMsg::default();
+    instantiate(
+        deps.as_mut(),
+        init_env.clone(),
+        init_info.clone(),
+        init_msg.clone(),
+    )
+    .expect("the canonical instantiate message must succeed");
+    let init_state_hash = deps.storage.state_hash();
+
+    let steps = canonical_sequence()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (sender, msg))| {
+            let env = vector_env(index as u64 + 1);
+            let info = mock_info(sender, &[]);
+            let success = replay(&mut deps, env.clone(), info.clone(), msg.clone());
+            TestVector {
+                env,
+                info,
+                msg,
+                success,
+                state_hash: deps.storage.state_hash(),
+            }
+        })
+        .collect();
+
+    TestVectors {
+        init_env,
+        init_info,
+        init_msg,
+        init_state_hash,
+        steps,
+    }
+}
+
+/// Replays `vectors` against this implementation. On a mismatch, returns the index of the
+/// first diverging step, where `0` is the instantiate message and `n` is `steps[n - 1]`.
+pub fn verify(vectors: &TestVectors) -> Result<(), usize> {
+    let mut deps = vector_dependencies();
+
+    instantiate(
+        deps.as_mut(),
+        vectors.init_env.clone(),
+        vectors.init_info.clone(),
+        vectors.init_msg.clone(),
+    )
+    .map_err(|_| 0usize)?;
+    if deps.storage.state_hash() != vectors.init_state_hash {
+        return Err(0);
+    }
+
+    for (index, step) in vectors.steps.iter().enumerate() {
+        let success = replay(
+            &mut deps,
+            step.env.clone(),
+            step.info.clone(),
+            step.msg.clone(),
+        );
+        if success != step.success || deps.storage.state_hash() != step.state_hash {
+            return Err(index + 1);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_vectors_replay() {
+        let vectors = generate();
+        assert_eq!(Ok(()), verify(&vectors));
+
+        // failed messages leave the state as it was
+        let carol_reset = &vectors.steps[6];
+        assert!(!carol_reset.success);
+        assert_eq!(vectors.steps[5].state_hash, carol_reset.state_hash);
+
+        let mut tampered = vectors.clone();
+        tampered.steps[3].msg = ExecuteMsg::Set {
+            label: None,
+            reencryption_key: Binary(vec![9; 32]),
+            expires_at: None,
+            description: None,
+            document_cid: None,
+        };
+        assert_eq!(Err(4), verify(&tampered));
+    }
+}
@@ -0,0 +1,205 @@
+//! Fixtures for contracts and off-chain services that integrate with this contract.
+//!
+//! `HodlerBuilder` instantiates the contract against mock dependencies and populates it
+//! by executing regular messages, so the resulting storage looks exactly like a deployed
+//! contract's. Viewing keys are the exception: the contract only hands out keys it
+//! generates, so fixtures store the predictable key `<address>_key` directly.
+//!
+//! This is built on `cosmwasm_std::testing`, so it is only available in native builds.
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{Api, Binary, Coin, Env, OwnedDeps};
+
+use crate::contract::{execute, instantiate};
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::state::{contract_config_read, write_viewing_key};
+use crate::viewing_key::{ViewingKey, SALT_SIZE};
+
+pub type MockDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+/// The viewing key fixtures store for `address`.
+pub fn mock_viewing_key(address: &str) -> ViewingKey {
+    ViewingKey(format!("{}_key", address))
+}
+
+/// Stores `mock_viewing_key(address)` as the viewing key of `address`.
+pub fn set_mock_viewing_key(deps: &mut MockDeps, address: &str) {
+    let address_raw = deps
+        .api
+        .addr_canonicalize(address)
+        .expect("fixture addresses must be valid");
+    write_viewing_key(
+        &mut deps.storage,
+        &address_raw,
+        &mock_viewing_key(address),
+        [0; SALT_SIZE],
+        mock_env().block.height,
+    )
+    .expect("the viewing key must be stored");
+}
+
+/// A query for the key `owner` (by default `address` itself) stored under `label`, read by
+/// `address` with its mock viewing key.
+pub fn mock_key_query(address: &str, owner: Option<&str>, label: Option<&str>) -> QueryMsg {
+    QueryMsg::GetReencryptionKey {
+        address: address.to_string(),
+        key: mock_viewing_key(address).0,
+        owner: owner.map(String::from),
+        label: label.map(String::from),
+    }
+}
+
+/// Builds a contract populated with keys, viewing keys, grants and dead-man switches.
+pub struct HodlerBuilder {
+    env: Env,
+    creator: String,
+    msg: InstantiateMsg,
+    viewing_keys: Vec<String>,
+    messages: Vec<(String, ExecuteMsg)>,
+}
+
+impl Default for HodlerBuilder {
+    fn default() -> Self {
+        HodlerBuilder {
+            env: mock_env(),
+            creator: "creator".to_string(),
+            msg: InstantiateMsg::default(),
+            viewing_keys: vec![],
+            messages: vec![],
+        }
+    }
+}
+
+impl HodlerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instantiates with `msg`, sent by `creator`.
+    pub fn instantiate_msg(mut self, creator: &str, msg: InstantiateMsg) -> Self {
+        self.creator = creator.to_string();
+        self.msg = msg;
+        self
+    }
+
+    /// Runs every message in `env` instead of `mock_env()`.
+    pub fn env(mut self, env: Env) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn viewing_key(mut self, address: &str) -> Self {
+        self.viewing_keys.push(address.to_string());
+        self
+    }
+
+    /// Stores `key` for `owner` under `label`, attaching the set fee configured at the time.
+    pub fn key(mut self, owner: &str, label: Option<&str>, key: Binary) -> Self {
+        self.messages.push((
+            owner.to_string(),
+            ExecuteMsg::Set {
+                label: label.map(String::from),
+                reencryption_key: key,
+                expires_at: None,
+                description: None,
+                document_cid: None,
+            },
+        ));
+        self
+    }
+
+    /// Delegates read access to `owner`'s keys to `grantee`.
+    pub fn grant(mut self, owner: &str, grantee: &str) -> Self {
+        self.messages.push((
+            owner.to_string(),
+            ExecuteMsg::Grant {
+                grantee: grantee.to_string(),
+            },
+        ));
+        self
+    }
+
+    pub fn dead_man_switch(
+        mut self,
+        owner: &str,
+        inactivity_window: u64,
+        beneficiaries: &[&str],
+    ) -> Self {
+        self.messages.push((
+            owner.to_string(),
+            ExecuteMsg::SetDeadManSwitch {
+                inactivity_window: Some(inactivity_window),
+                beneficiaries: beneficiaries.iter().map(|b| b.to_string()).collect(),
+            },
+        ));
+        self
+    }
+
+    /// Queues any other message, sent by `sender`.
+    pub fn execute(mut self, sender: &str, msg: ExecuteMsg) -> Self {
+        self.messages.push((sender.to_string(), msg));
+        self
+    }
+
+    /// Instantiates the contract and runs the queued messages in order. Panics if any
+    /// of them fail, so a fixture never silently differs from what it describes.
+    pub fn build(self) -> MockDeps {
+        let mut deps = mock_dependencies();
+
+        let info = mock_info(&self.creator, &[]);
+        instantiate(deps.as_mut(), self.env.clone(), info, self.msg)
+            .expect("the fixture's instantiate message must succeed");
+        for address in self.viewing_keys.iter() {
+            set_mock_viewing_key(&mut deps, address);
+        }
+        for (sender, msg) in self.messages {
+            // the fee may have been changed by an earlier message
+            let funds: Vec<Coin> = match msg {
+                ExecuteMsg::Set { .. } => contract_config_read(&deps.storage)
+                    .load()
+                    .expect("the contract must be configured")
+                    .set_fee
+                    .into_iter()
+                    .collect(),
+                _ => vec![],
+            };
+            let info = mock_info(&sender, &funds);
+            if let Err(err) = execute(deps.as_mut(), self.env.clone(), info, msg.clone()) {
+                panic!("fixture message {:?} from {} failed: {}", msg, sender, err);
+            }
+        }
+        deps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{coin, from_binary};
+
+    use crate::contract::query;
+    use crate::msg::ReencryptionKeyResponse;
+
+    #[test]
+    fn built_state_is_readable() {
+        let deps = HodlerBuilder::new()
+            .instantiate_msg(
+                "admin",
+                InstantiateMsg {
+                    set_fee: Some(coin(10, "uscrt")),
+                    ..InstantiateMsg::default()
+                },
+            )
+            .viewing_key("reader")
+            .key("owner", Some("will"), Binary(vec![3; 32]))
+            .grant("owner", "reader")
+            .dead_man_switch("owner", 100, &["reader"])
+            .build();
+
+        let msg = mock_key_query("reader", Some("owner"), Some("will"));
+        let res = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: ReencryptionKeyResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Binary(vec![3; 32])), value.reencryption_key);
+    }
+}
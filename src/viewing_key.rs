@@ -0,0 +1,84 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use cosmwasm_std::{Binary, Env, MessageInfo};
+
+pub const VIEWING_KEY_SIZE: usize = 32;
+pub const SALT_SIZE: usize = 16;
+const VIEWING_KEY_PREFIX: &str = "api_key_";
+const RETRIEVAL_TOKEN_PREFIX: &str = "retrieval_";
+
+/// A SNIP-20 style viewing key. Keys are only ever generated by the contract, and only a
+/// salted sha256 hash of them is written to storage.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct ViewingKey(pub String);
+
+impl ViewingKey {
+    /// Derives a new key from the contract's seed, the block and its randomness, the sender
+    /// and caller-supplied entropy.
+    pub fn new(env: &Env, info: &MessageInfo, seed: &[u8], entropy: &[u8]) -> Self {
+        let key = new_secret(env, info, seed, entropy);
+        Self(VIEWING_KEY_PREFIX.to_string() + &key.to_base64())
+    }
+
+    pub fn to_hashed(&self, salt: &[u8]) -> [u8; VIEWING_KEY_SIZE] {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(self.0.as_bytes());
+        let mut hashed = [0u8; VIEWING_KEY_SIZE];
+        hashed.copy_from_slice(&hasher.finalize());
+        hashed
+    }
+
+    pub fn check_viewing_key(&self, salt: &[u8], hashed_key: &[u8]) -> bool {
+        let mine_hashed = self.to_hashed(salt);
+        ct_slice_compare(&mine_hashed, hashed_key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl fmt::Display for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Derives a new single-use retrieval token the same way as a viewing key.
+pub fn new_retrieval_token(env: &Env, info: &MessageInfo, seed: &[u8], entropy: &[u8]) -> String {
+    let token = new_secret(env, info, seed, entropy);
+    RETRIEVAL_TOKEN_PREFIX.to_string() + &token.to_base64()
+}
+
+fn new_secret(env: &Env, info: &MessageInfo, seed: &[u8], entropy: &[u8]) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(env.block.height.to_be_bytes());
+    hasher.update(env.block.time.seconds().to_be_bytes());
+    if let Some(random) = &env.block.random {
+        hasher.update(random.as_slice());
+    }
+    hasher.update(info.sender.as_bytes());
+    hasher.update(entropy);
+    Binary(hasher.finalize().to_vec())
+}
+
+/// Derives the salt for a key hash from the contract's seed, so no two keys share one.
+pub fn new_salt(seed: &[u8]) -> [u8; SALT_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"salt");
+    hasher.update(seed);
+    let mut salt = [0u8; SALT_SIZE];
+    salt.copy_from_slice(&hasher.finalize()[..SALT_SIZE]);
+    salt
+}
+
+pub fn ct_slice_compare(s1: &[u8], s2: &[u8]) -> bool {
+    bool::from(s1.ct_eq(s2))
+}